@@ -0,0 +1,234 @@
+//! `crawl_x crawl`, `crawl_x reparse`, `crawl_x export`, `crawl_x
+//! import` and `crawl_x report` on the database of `APP_PROFILE`
+//!
+//! `crawl` crawls X_based_china into astroturfers_x as one crawl run,
+//! `reparse` rebuilds the rows from the archived pages without any
+//! network access, see `crawl_x::crawl_1`. A reparse on prod also
+//! needs `--confirm-prod`.
+//!
+//! `export` writes astroturfers_x to a .csv, .jsonl or .parquet file
+//! and its manifest, see `crawl_x::export`. `import` reads a .csv or
+//! .jsonl file back, see `crawl_x::import`. An import on prod also
+//! needs `--confirm-prod`, unless it is a `--dry-run`. `report` counts
+//! the rows of the same filters as `export`, see `crawl_x::report`.
+use excavate::crawl_x::crawl_1::EndToDB;
+use excavate::crawl_x::db;
+use excavate::crawl_x::export::{self, Filter};
+use excavate::crawl_x::import::{self, Conflict};
+use excavate::crawl_x::report::{self, Interval, Output};
+use std::path::PathBuf;
+use utils::config::env::ENV_SETTINGS;
+use utils::tools::log::init_logger;
+
+const USAGE: &str = "usage: crawl_x crawl
+       crawl_x reparse [--confirm-prod]
+       crawl_x export <file.csv|file.jsonl|file.parquet> \
+    [--registered-from YYYY-MM-DD] [--registered-to YYYY-MM-DD] \
+    [--min-changed-names N] [--region R] [--include-removed]
+       crawl_x import <file.csv|file.jsonl> \
+    [--conflict keep-newest|keep-existing|overwrite] [--dry-run] \
+    [--report <file.json>] [--confirm-prod]
+       crawl_x report [--format text|json|html] [--interval day|week] \
+    [--top N] [--output <file>] [export filters]";
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    init_logger();
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        Some("crawl") => {
+            if args.next().is_some() {
+                anyhow::bail!(USAGE);
+            }
+            EndToDB::end().await?;
+        }
+        Some("reparse") => {
+            let confirmed = match args.next().as_deref() {
+                None => false,
+                Some("--confirm-prod") => true,
+                Some(_) => anyhow::bail!(USAGE),
+            };
+            if args.next().is_some() {
+                anyhow::bail!(USAGE);
+            }
+            EndToDB::reparse(confirmed).await?;
+        }
+        Some("export") => {
+            let path = args
+                .next()
+                .map(PathBuf::from)
+                .ok_or_else(|| anyhow::anyhow!(USAGE))?;
+            let filter = filter(args)?;
+            let db =
+                db::connect(ENV_SETTINGS.db_url()?).await?;
+            let manifest =
+                export::export(&db, &filter, &path).await?;
+            println!(
+                "{} rows, sha256 {}, manifest {}",
+                manifest.rows,
+                manifest.sha256,
+                export::manifest_path(&path).display()
+            );
+        }
+        Some("import") => {
+            let path = args
+                .next()
+                .map(PathBuf::from)
+                .ok_or_else(|| anyhow::anyhow!(USAGE))?;
+            import(path, args).await?;
+        }
+        Some("report") => report(args).await?,
+        _ => anyhow::bail!(USAGE),
+    }
+    Ok(())
+}
+
+/// `crawl_x import`, the report on stdout and in `--report`
+async fn import(
+    path: PathBuf,
+    mut args: impl Iterator<Item = String>,
+) -> anyhow::Result<()> {
+    let mut conflict = Conflict::default();
+    let mut dry_run = false;
+    let mut confirmed = false;
+    let mut report_path = None;
+    while let Some(flag) = args.next() {
+        match flag.as_str() {
+            "--dry-run" => dry_run = true,
+            "--confirm-prod" => confirmed = true,
+            "--conflict" => {
+                conflict = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!(USAGE))?
+                    .parse()?
+            }
+            "--report" => {
+                report_path =
+                    Some(args.next().ok_or_else(|| {
+                        anyhow::anyhow!(USAGE)
+                    })?)
+            }
+            _ => anyhow::bail!(USAGE),
+        }
+    }
+    // every strategy writes rows, a dry run writes nothing
+    if !dry_run {
+        ENV_SETTINGS
+            .guard_destructive("import", confirmed)?;
+    }
+    let db = db::connect(ENV_SETTINGS.db_url()?).await?;
+    let report =
+        import::import(&db, &path, conflict, dry_run)
+            .await?;
+    for error in &report.errors {
+        println!(
+            "line {} {}: {}",
+            error.line,
+            error.user_id.as_deref().unwrap_or("-"),
+            error.problems.join("; ")
+        );
+    }
+    println!(
+        "{}{} rows, {} written, {} kept, {} errors",
+        if dry_run { "dry run: " } else { "" },
+        report.rows,
+        report.written,
+        report.kept,
+        report.errors.len()
+    );
+    if let Some(report_path) = report_path {
+        std::fs::write(
+            report_path,
+            serde_json::to_string_pretty(&report)?,
+        )?;
+    }
+    Ok(())
+}
+
+/// `crawl_x report`, on stdout unless `--output`
+async fn report(
+    mut args: impl Iterator<Item = String>,
+) -> anyhow::Result<()> {
+    let mut filter = Filter::default();
+    let mut output = Output::default();
+    let mut interval = Interval::default();
+    let mut top = report::DEFAULT_TOP;
+    let mut path = None;
+    while let Some(flag) = args.next() {
+        if filter_flag(&mut filter, &flag, &mut args)? {
+            continue;
+        }
+        let value = args.next().ok_or_else(|| {
+            anyhow::anyhow!(
+                "{} needs a value, {}",
+                flag,
+                USAGE
+            )
+        })?;
+        match flag.as_str() {
+            "--format" => output = value.parse()?,
+            "--interval" => interval = value.parse()?,
+            "--top" => top = value.parse()?,
+            "--output" => path = Some(value),
+            _ => anyhow::bail!(USAGE),
+        }
+    }
+    let db = db::connect(ENV_SETTINGS.db_url()?).await?;
+    let rendered =
+        report::report(&db, &filter, interval, top)
+            .await?
+            .render(output)?;
+    match path {
+        Some(path) => std::fs::write(path, rendered)?,
+        None => print!("{}", rendered),
+    }
+    Ok(())
+}
+
+/// the filter flags after the file
+fn filter(
+    mut args: impl Iterator<Item = String>,
+) -> anyhow::Result<Filter> {
+    let mut filter = Filter::default();
+    while let Some(flag) = args.next() {
+        if !filter_flag(&mut filter, &flag, &mut args)? {
+            anyhow::bail!(USAGE);
+        }
+    }
+    Ok(filter)
+}
+
+/// `flag` and its value into `filter`, false for other flags
+fn filter_flag(
+    filter: &mut Filter,
+    flag: &str,
+    args: &mut impl Iterator<Item = String>,
+) -> anyhow::Result<bool> {
+    let mut value = || {
+        args.next().ok_or_else(|| {
+            anyhow::anyhow!(
+                "{} needs a value, {}",
+                flag,
+                USAGE
+            )
+        })
+    };
+    match flag {
+        "--include-removed" => {
+            filter.include_removed = true
+        }
+        "--registered-from" => {
+            filter.registered_from = Some(value()?.parse()?)
+        }
+        "--registered-to" => {
+            filter.registered_to = Some(value()?.parse()?)
+        }
+        "--min-changed-names" => {
+            filter.min_changed_name_count =
+                Some(value()?.parse()?)
+        }
+        "--region" => filter.region = Some(value()?),
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
@@ -0,0 +1,676 @@
+//! generic html list extractor driven by a TOML spec
+//!
+//! A spec names the container of one record, a selector per field and
+//! an optional "label：value" meta block, e.g.
+//!
+//! ```toml
+//! container = "article.user-card"
+//!
+//! [fields.user_id]
+//! selector = "div.user-id"
+//! transforms = ["trim", { strip_prefix = "ID: " }]
+//!
+//! [fields.avatar]
+//! selector = "img.user-avatar"
+//! attr = "src"
+//!
+//! [meta]
+//! selector = "div.user-meta span"
+//! separator = "："
+//! labels = { "地区" = "region" }
+//! ```
+//!
+//! Structs known at compile time can `#[derive(Extract)]` instead, see
+//! `extract_derive`; their selectors are checked while compiling and
+//! bad values are an [`ExtractError`] rather than an empty string.
+use anyhow::Context;
+use scraper::{ElementRef, Html, Selector};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+
+pub use extract_derive::Extract;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExtractorSpec {
+    /// one match per record
+    pub container: String,
+    #[serde(default)]
+    pub fields: BTreeMap<String, FieldSpec>,
+    pub meta: Option<MetaSpec>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FieldSpec {
+    /// first match inside the container
+    pub selector: String,
+    /// read this attribute instead of the text
+    pub attr: Option<String>,
+    #[serde(default = "default_transforms")]
+    pub transforms: Vec<Transform>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MetaSpec {
+    /// every match is one "label：value" item
+    pub selector: String,
+    #[serde(default = "default_separator")]
+    pub separator: String,
+    /// label on the page to field name
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+/// applied in order to the raw value of a field
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transform {
+    Trim,
+    StripPrefix(String),
+    StripSuffix(String),
+    Replace { from: String, to: String },
+}
+
+fn default_transforms() -> Vec<Transform> {
+    vec![Transform::Trim]
+}
+
+fn default_separator() -> String {
+    "：".to_string()
+}
+
+impl Transform {
+    fn apply(&self, value: String) -> String {
+        match self {
+            Transform::Trim => value.trim().to_string(),
+            Transform::StripPrefix(prefix) => value
+                .strip_prefix(prefix.as_str())
+                .map(String::from)
+                .unwrap_or(value),
+            Transform::StripSuffix(suffix) => value
+                .strip_suffix(suffix.as_str())
+                .map(String::from)
+                .unwrap_or(value),
+            Transform::Replace { from, to } => {
+                value.replace(from.as_str(), to)
+            }
+        }
+    }
+}
+
+/// values of one container, by field name
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Record {
+    pub fields: BTreeMap<String, String>,
+    /// meta labels the spec does not know, as "label：value"
+    pub unknown_meta: Vec<String>,
+}
+
+impl Record {
+    /// value of `field`, empty when the page had none
+    pub fn get(&self, field: &str) -> &str {
+        self.fields.get(field).map_or("", String::as_str)
+    }
+
+    pub fn take(&mut self, field: &str) -> String {
+        self.fields.remove(field).unwrap_or_default()
+    }
+}
+
+struct Field {
+    name: String,
+    selector: Selector,
+    attr: Option<String>,
+    transforms: Vec<Transform>,
+}
+
+struct Meta {
+    selector: Selector,
+    separator: String,
+    labels: HashMap<String, String>,
+}
+
+/// a spec with its selectors parsed
+pub struct Extractor {
+    container: Selector,
+    fields: Vec<Field>,
+    meta: Option<Meta>,
+}
+
+fn parse_selector(
+    css: &str,
+    of: &str,
+) -> anyhow::Result<Selector> {
+    Selector::parse(css).map_err(|e| {
+        anyhow::anyhow!(
+            "invalid selector {:?} of {}: {}",
+            css,
+            of,
+            e
+        )
+    })
+}
+
+impl Extractor {
+    /// parse every selector of `spec`, the first invalid one fails
+    pub fn from_spec(
+        spec: ExtractorSpec,
+    ) -> anyhow::Result<Self> {
+        let container =
+            parse_selector(&spec.container, "container")?;
+        let fields = spec
+            .fields
+            .into_iter()
+            .map(|(name, field)| {
+                Ok(Field {
+                    selector: parse_selector(
+                        &field.selector,
+                        &name,
+                    )?,
+                    name,
+                    attr: field.attr,
+                    transforms: field.transforms,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let meta = spec
+            .meta
+            .map(|meta| {
+                anyhow::Ok(Meta {
+                    selector: parse_selector(
+                        &meta.selector,
+                        "meta",
+                    )?,
+                    separator: meta.separator,
+                    labels: meta
+                        .labels
+                        .into_iter()
+                        .collect(),
+                })
+            })
+            .transpose()?;
+        Ok(Self {
+            container,
+            fields,
+            meta,
+        })
+    }
+
+    pub fn from_toml(spec: &str) -> anyhow::Result<Self> {
+        Self::from_spec(toml::from_str(spec)?)
+    }
+
+    pub fn from_file(
+        path: impl AsRef<Path>,
+    ) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let spec = std::fs::read_to_string(path)
+            .with_context(|| {
+                format!("read {}", path.display())
+            })?;
+        Self::from_toml(&spec).with_context(|| {
+            format!("load {}", path.display())
+        })
+    }
+
+    /// one record per container in `html`
+    pub fn extract(&self, html: &str) -> Vec<Record> {
+        let doc = Html::parse_document(html);
+        doc.select(&self.container)
+            .map(|container| self.record(container))
+            .collect()
+    }
+
+    fn record(&self, container: ElementRef) -> Record {
+        let mut record = Record::default();
+        for field in &self.fields {
+            let Some(element) =
+                container.select(&field.selector).next()
+            else {
+                continue;
+            };
+            let raw = match &field.attr {
+                Some(attr) => element
+                    .value()
+                    .attr(attr)
+                    .unwrap_or_default()
+                    .to_string(),
+                None => element.text().collect(),
+            };
+            let value = field
+                .transforms
+                .iter()
+                .fold(raw, |value, transform| {
+                    transform.apply(value)
+                });
+            record.fields.insert(field.name.clone(), value);
+        }
+        if let Some(meta) = &self.meta {
+            for item in container.select(&meta.selector) {
+                let item = item.text().collect::<String>();
+                let item = item.trim();
+                let known = item
+                    .split_once(meta.separator.as_str())
+                    .and_then(|(label, value)| {
+                        Some((
+                            meta.labels
+                                .get(label.trim())?,
+                            value,
+                        ))
+                    });
+                match known {
+                    Some((name, value)) => {
+                        record.fields.insert(
+                            name.clone(),
+                            value.trim().to_string(),
+                        );
+                    }
+                    None => record
+                        .unknown_meta
+                        .push(item.to_string()),
+                }
+            }
+        }
+        record
+    }
+}
+
+/// why a container could not become a typed record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExtractError {
+    /// nothing matched, or the value was empty
+    Missing {
+        field: &'static str,
+        /// selector or meta label of the field
+        source: &'static str,
+    },
+    /// the value does not parse into the field type
+    Invalid {
+        field: &'static str,
+        value: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for ExtractError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            ExtractError::Missing { field, source } => {
+                write!(f, "{field}: nothing at {source:?}")
+            }
+            ExtractError::Invalid {
+                field,
+                value,
+                reason,
+            } => write!(
+                f,
+                "{field}: {value:?} is invalid, {reason}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ExtractError {}
+
+/// a struct read from one container, implemented by `#[derive(Extract)]`
+pub trait Extract: Sized {
+    /// one match per record
+    const CONTAINER: &'static str;
+
+    fn extract(
+        element: ElementRef<'_>,
+    ) -> Result<Self, ExtractError>;
+
+    /// meta items of `element` no field reads, as "label：value"
+    fn unknown_meta(
+        _element: ElementRef<'_>,
+    ) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// one result per container in `html`
+    fn extract_all(
+        html: &str,
+    ) -> Vec<Result<Self, ExtractError>> {
+        let container = Selector::parse(Self::CONTAINER)
+            .expect("checked by #[derive(Extract)]");
+        Html::parse_document(html)
+            .select(&container)
+            .map(Self::extract)
+            .collect()
+    }
+
+    /// every container in `html`, sorted into records and errors
+    fn extract_page(html: &str) -> Page<Self> {
+        let container = Selector::parse(Self::CONTAINER)
+            .expect("checked by #[derive(Extract)]");
+        let mut page = Page::default();
+        for element in
+            Html::parse_document(html).select(&container)
+        {
+            page.unknown_meta
+                .extend(Self::unknown_meta(element));
+            match Self::extract(element) {
+                Ok(record) => page.records.push(record),
+                Err(e) => page.errors.push(e),
+            }
+        }
+        page
+    }
+}
+
+/// what the containers of one page yielded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page<T> {
+    pub records: Vec<T>,
+    /// one per container that did not become a record
+    pub errors: Vec<ExtractError>,
+    /// meta items no field reads, over all containers
+    pub unknown_meta: Vec<String>,
+}
+
+impl<T> Default for Page<T> {
+    fn default() -> Self {
+        Self {
+            records: Vec::new(),
+            errors: Vec::new(),
+            unknown_meta: Vec::new(),
+        }
+    }
+}
+
+impl<T> Page<T> {
+    /// containers on the page
+    pub fn containers(&self) -> usize {
+        self.records.len() + self.errors.len()
+    }
+}
+
+/// used by the code `#[derive(Extract)]` generates
+#[doc(hidden)]
+pub mod __private {
+    use super::ExtractError;
+    pub use scraper::{ElementRef, Selector};
+    use std::fmt::Display;
+    use std::str::FromStr;
+    pub use std::sync::LazyLock;
+
+    pub fn select_text(
+        element: ElementRef<'_>,
+        selector: &Selector,
+    ) -> Option<String> {
+        element
+            .select(selector)
+            .next()
+            .map(|e| e.text().collect())
+    }
+
+    pub fn select_attr(
+        element: ElementRef<'_>,
+        selector: &Selector,
+        attr: &str,
+    ) -> Option<String> {
+        element
+            .select(selector)
+            .next()?
+            .value()
+            .attr(attr)
+            .map(String::from)
+    }
+
+    /// trimmed (label, value) of every "label：value" item
+    pub fn meta_items(
+        element: ElementRef<'_>,
+        selector: &Selector,
+        separator: &str,
+    ) -> Vec<(String, String)> {
+        element
+            .select(selector)
+            .filter_map(|item| {
+                let item = item.text().collect::<String>();
+                let (label, value) =
+                    item.split_once(separator)?;
+                Some((
+                    label.trim().to_string(),
+                    value.trim().to_string(),
+                ))
+            })
+            .collect()
+    }
+
+    /// trimmed items whose label is not in `known`
+    pub fn unknown_meta(
+        element: ElementRef<'_>,
+        selector: &Selector,
+        separator: &str,
+        known: &[&str],
+    ) -> Vec<String> {
+        element
+            .select(selector)
+            .filter_map(|item| {
+                let item = item.text().collect::<String>();
+                let item = item.trim();
+                match item.split_once(separator) {
+                    Some((label, _))
+                        if known
+                            .contains(&label.trim()) =>
+                    {
+                        None
+                    }
+                    _ => Some(item.to_string()),
+                }
+            })
+            .collect()
+    }
+
+    pub fn meta_value(
+        items: &[(String, String)],
+        label: &str,
+    ) -> Option<String> {
+        items
+            .iter()
+            .find(|(l, _)| l == label)
+            .map(|(_, value)| value.clone())
+    }
+
+    pub fn strip_prefix(
+        value: String,
+        prefix: &str,
+    ) -> String {
+        value
+            .strip_prefix(prefix)
+            .map(String::from)
+            .unwrap_or(value)
+    }
+
+    pub fn parse<T>(
+        field: &'static str,
+        value: String,
+    ) -> Result<T, ExtractError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        convert(field, value, str::parse)
+    }
+
+    pub fn convert<T, E: Display>(
+        field: &'static str,
+        value: String,
+        with: impl FnOnce(&str) -> Result<T, E>,
+    ) -> Result<T, ExtractError> {
+        with(&value).map_err(|e| ExtractError::Invalid {
+            field,
+            reason: e.to_string(),
+            value,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SPEC: &str = r##"
+container = "li.item"
+
+[fields.title]
+selector = "h3"
+
+[fields.link]
+selector = "a"
+attr = "href"
+transforms = [{ strip_prefix = "https://" }]
+
+[fields.id]
+selector = ".id"
+transforms = ["trim", { strip_prefix = "#" }, { replace = { from = "-", to = "" } }]
+
+[meta]
+selector = ".meta span"
+labels = { "城市" = "city" }
+"##;
+
+    const HTML: &str = r#"
+<ul>
+  <li class="item">
+    <h3>  first  </h3>
+    <a href="https://example.com/1">go</a>
+    <span class="id"> #12-34 </span>
+    <p class="meta"><span>城市：上海</span><span>颜色：红</span><span>free text</span></p>
+  </li>
+  <li class="item"><h3>second</h3></li>
+</ul>
+"#;
+
+    #[test]
+    fn extract_records() -> anyhow::Result<()> {
+        let extractor = Extractor::from_toml(SPEC)?;
+        let records = extractor.extract(HTML);
+        assert_eq!(records.len(), 2);
+        let first = &records[0];
+        assert_eq!(first.get("title"), "first");
+        assert_eq!(first.get("link"), "example.com/1");
+        assert_eq!(first.get("id"), "1234");
+        assert_eq!(first.get("city"), "上海");
+        assert_eq!(
+            first.unknown_meta,
+            ["颜色：红", "free text"]
+        );
+
+        let second = &records[1];
+        assert_eq!(second.get("title"), "second");
+        assert_eq!(second.get("link"), "");
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_selector_fails_on_load() {
+        let spec = r#"
+container = "li.item"
+[fields.title]
+selector = "h3[["
+"#;
+        let err = Extractor::from_toml(spec).err().unwrap();
+        assert!(
+            err.to_string().contains("of title"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn unknown_transform_fails_on_load() {
+        let spec = r#"
+container = "li"
+[fields.title]
+selector = "h3"
+transforms = ["shout"]
+"#;
+        assert!(Extractor::from_toml(spec).is_err());
+    }
+
+    #[derive(Debug, PartialEq, Extract)]
+    #[extract(
+        container = "li.item",
+        meta(selector = ".meta span")
+    )]
+    struct Item {
+        #[extract(selector = "h3", text, trim)]
+        title: String,
+        #[extract(
+            selector = ".id",
+            trim,
+            strip_prefix = "#"
+        )]
+        id: u32,
+        #[extract(selector = "a", attr = "href")]
+        link: Option<String>,
+        #[extract(meta = "城市", default)]
+        city: String,
+        #[extract(skip)]
+        page: String,
+    }
+
+    #[test]
+    fn derive_extract() {
+        let html = r#"
+<ul>
+  <li class="item">
+    <h3> first </h3><span class="id"> #12 </span>
+    <a href="/1">go</a>
+    <p class="meta"><span>城市：上海</span></p>
+  </li>
+  <li class="item"><h3>second</h3><p class="meta"><span>颜色：红</span></p><span class="id">#2</span></li>
+  <li class="item"><h3> </h3><span class="id">#3</span></li>
+  <li class="item"><h3>fourth</h3><span class="id">#x</span></li>
+</ul>
+"#;
+        let items = Item::extract_all(html);
+        assert_eq!(items.len(), 4);
+        assert_eq!(
+            items[0],
+            Ok(Item {
+                title: "first".to_string(),
+                id: 12,
+                link: Some("/1".to_string()),
+                city: "上海".to_string(),
+                page: String::new(),
+            })
+        );
+        let second = items[1].as_ref().unwrap();
+        assert_eq!(
+            (second.link.as_ref(), second.city.as_str()),
+            (None, "")
+        );
+        assert_eq!(
+            items[2],
+            Err(ExtractError::Missing {
+                field: "title",
+                source: "h3",
+            })
+        );
+        let page = Item::extract_page(html);
+        assert_eq!(
+            (page.records.len(), page.containers()),
+            (2, 4)
+        );
+        assert_eq!(page.unknown_meta, ["颜色：红"]);
+        assert!(matches!(
+            &items[3],
+            Err(ExtractError::Invalid { field: "id", value, .. }) if value == "x"
+        ));
+    }
+
+    /// the selector and attribute checks of the derive, see
+    /// `tests/ui`
+    #[test]
+    fn derive_rejects_at_compile_time() {
+        trybuild::TestCases::new()
+            .compile_fail("tests/ui/*.rs");
+    }
+}
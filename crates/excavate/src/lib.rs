@@ -1,4 +1,10 @@
+// lets `#[derive(Extract)]` name `::excavate` inside this crate
+extern crate self as excavate;
+
+pub mod extract;
 mod practice;
+// for the `crawl_x` binary
+pub use practice::crawl_x;
 pub fn add(left: u64, right: u64) -> u64 {
     left + right
 }
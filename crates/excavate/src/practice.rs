@@ -2,4 +2,4 @@
 mod official_1;
 mod spider1;
 mod spider_chrome_direct;
-mod crawl_x;
+pub mod crawl_x;
@@ -1,2 +1,15 @@
-mod crawl_1;
+mod bulk;
+pub mod crawl_1;
+mod crawl_run;
+pub mod db;
+pub mod export;
+mod health;
+mod history;
+mod http_cache;
+pub mod import;
+mod normalize;
+mod quarantine;
+pub mod report;
+mod snapshot;
 mod to_db;
+mod validate;
@@ -0,0 +1,696 @@
+//! batched writes of many pages to astroturfers_x, for crawls and
+//! backfills
+//!
+//! Rows are buffered across pages and written in one transaction per
+//! batch, together with the unchanged pages to mark as seen. Batches
+//! of at least `copy_min_rows` rows on Postgres are COPYed into a
+//! staging table and merged from there, with the same `ON CONFLICT`
+//! as `to_db::upsert`.
+use super::to_db::{
+    self, COLUMNS, Column, Model, csv_line, fields,
+};
+use super::{history, http_cache, quarantine};
+use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::{
+    Alias, Expr, OnConflict, Order, PostgresQueryBuilder,
+    Query,
+};
+use sea_orm::sqlx;
+use sea_orm::{
+    DatabaseBackend, QueryTrait, TransactionTrait,
+    UpdateMany,
+};
+use utils::config::env::ENV_SETTINGS;
+use utils::tools::fetch::Validators;
+
+/// rows buffered before a batch is written
+const DEFAULT_BATCH_ROWS: usize = 5_000;
+/// smallest batch worth a staging table
+const DEFAULT_COPY_MIN_ROWS: usize = 20_000;
+/// rows per message of a COPY
+const COPY_CHUNK_ROWS: usize = 1_000;
+/// session local table of the COPY path, dropped on commit
+const STAGING: &str = "astroturfers_x_staging";
+
+/// rows parsed from one page
+#[derive(Debug, Clone)]
+pub struct PageRows {
+    pub url: String,
+    /// run the rows are seen by
+    pub run_id: i64,
+    pub seen_at: DateTimeUtc,
+    pub models: Vec<Model>,
+    /// cached for `url` once the rows are in, `None` leaves the cache
+    pub validators: Option<Validators>,
+}
+
+/// buffers pages and writes them in batches
+pub struct BulkWriter<'a> {
+    db: &'a DatabaseConnection,
+    batch_rows: usize,
+    copy_min_rows: usize,
+    pages: Vec<PageRows>,
+    /// unchanged pages and the run that saw them
+    touched: Vec<(i64, String)>,
+    rows: usize,
+    /// rows of archived pages, see `reparse`
+    reparse: bool,
+}
+
+impl<'a> BulkWriter<'a> {
+    /// batch sizes from `BULK_BATCH_ROWS` / `BULK_COPY_MIN_ROWS`
+    pub fn new(db: &'a DatabaseConnection) -> Self {
+        Self {
+            db,
+            batch_rows: ENV_SETTINGS
+                .bulk_batch_rows
+                .unwrap_or(DEFAULT_BATCH_ROWS),
+            copy_min_rows: ENV_SETTINGS
+                .bulk_copy_min_rows
+                .unwrap_or(DEFAULT_COPY_MIN_ROWS),
+            pages: Vec::new(),
+            touched: Vec::new(),
+            rows: 0,
+            reparse: false,
+        }
+    }
+
+    /// write everything on the next `flush` only
+    pub fn unbatched(mut self) -> Self {
+        self.batch_rows = usize::MAX;
+        self
+    }
+
+    /// The pages are re-parsed snapshots, not fetches of this run. Only
+    /// `to_db::REPARSE_COLUMNS` are rewritten, the history and the
+    /// quarantine are not.
+    pub fn reparse(mut self) -> Self {
+        self.reparse = true;
+        self
+    }
+
+    /// queue `page`, true once the batch is full and due for `flush`
+    pub fn push(&mut self, page: PageRows) -> bool {
+        self.rows += page.models.len();
+        self.pages.push(page);
+        self.rows >= self.batch_rows
+    }
+
+    /// queue unchanged page `url`, its rows are seen by run `run_id`
+    pub fn touch(&mut self, run_id: i64, url: String) {
+        self.touched.push((run_id, url));
+    }
+
+    /// pages queued since the last `flush`
+    pub fn pending_pages(&self) -> u32 {
+        (self.pages.len() + self.touched.len()) as u32
+    }
+
+    /// drop the queued pages unwritten, returns how many
+    pub fn discard(&mut self) -> u32 {
+        let pages = self.pending_pages();
+        self.pages.clear();
+        self.touched.clear();
+        self.rows = 0;
+        pages
+    }
+
+    /// Write the queued pages, returns how many rows were upserted. The
+    /// queue is emptied even when the write fails.
+    pub async fn flush(&mut self) -> anyhow::Result<u32> {
+        if self.pending_pages() == 0 {
+            return Ok(0);
+        }
+        self.rows = 0;
+        let batch = Batch::of(
+            std::mem::take(&mut self.pages),
+            std::mem::take(&mut self.touched),
+            self.reparse,
+        );
+        let validators = batch.validators.clone();
+        let copy = batch.rows.len() >= self.copy_min_rows
+            && self.db.get_database_backend()
+                == DatabaseBackend::Postgres;
+        let count = if copy {
+            batch.copy_merge(self.db).await?
+        } else {
+            batch.insert(self.db).await?
+        };
+        // only once the rows are in, or a failed page is skipped as
+        // unchanged next time
+        for (url, validators) in &validators {
+            http_cache::store(self.db, url, validators)
+                .await?;
+        }
+        Ok(count)
+    }
+}
+
+/// the queued pages of a flush, validated and stamped
+struct Batch {
+    /// valid rows and when they were seen, in page order
+    rows: Vec<(DateTimeUtc, Model)>,
+    /// invalid rows of a page with their run and time
+    rejected:
+        Vec<(i64, DateTimeUtc, Vec<(Model, Vec<String>)>)>,
+    validators: Vec<(String, Validators)>,
+    touched: Vec<(i64, String)>,
+    reparse: bool,
+}
+
+impl Batch {
+    fn of(
+        pages: Vec<PageRows>,
+        touched: Vec<(i64, String)>,
+        reparse: bool,
+    ) -> Self {
+        let mut batch = Self {
+            rows: Vec::new(),
+            rejected: Vec::new(),
+            validators: Vec::new(),
+            touched,
+            reparse,
+        };
+        for page in pages {
+            let (models, rejected) =
+                to_db::split_invalid(page.models);
+            // quarantined by the run that fetched the page already
+            if !rejected.is_empty() && !reparse {
+                batch.rejected.push((
+                    page.run_id,
+                    page.seen_at,
+                    rejected,
+                ));
+            }
+            batch.rows.extend(
+                to_db::stamp(
+                    page.run_id,
+                    page.seen_at,
+                    models,
+                )
+                .into_iter()
+                .map(|model| (page.seen_at, model)),
+            );
+            if let Some(validators) = page.validators {
+                batch
+                    .validators
+                    .push((page.url, validators));
+            }
+        }
+        batch
+    }
+
+    /// Marks the stored accounts of quarantined rows as seen by the
+    /// run that quarantined them. Re-parsed pages quarantine nothing.
+    fn touch_quarantined(
+        &self,
+    ) -> Vec<UpdateMany<to_db::Entity>> {
+        self.rejected
+            .iter()
+            .map(|(run_id, _, rejected)| {
+                to_db::touch_accounts_query(
+                    *run_id,
+                    rejected.iter().map(|(model, _)| {
+                        model.user_id.as_str()
+                    }),
+                )
+            })
+            .collect()
+    }
+
+    /// insert statements under the parameter limit, one transaction
+    async fn insert(
+        self,
+        db: &DatabaseConnection,
+    ) -> anyhow::Result<u32> {
+        let txn = db.begin().await?;
+        for (run_id, at, rejected) in &self.rejected {
+            quarantine::store(&txn, *run_id, *at, rejected)
+                .await?;
+        }
+        for touch in self.touch_quarantined() {
+            touch.exec(&txn).await?;
+        }
+        if !self.reparse {
+            for seen in
+                self.rows.chunk_by(|a, b| a.0 == b.0)
+            {
+                history::record(
+                    &txn,
+                    seen.iter().map(|(_, model)| model),
+                    seen[0].0,
+                )
+                .await?;
+            }
+        }
+        let models: Vec<Model> = self
+            .rows
+            .into_iter()
+            .map(|(_, model)| model)
+            .collect();
+        let count = if self.reparse {
+            to_db::upsert_reparsed(&txn, models).await?
+        } else {
+            to_db::upsert(&txn, models).await?
+        };
+        for (run_id, url) in &self.touched {
+            let seen =
+                to_db::touch_page(&txn, *run_id, url)
+                    .await?;
+            log::info!(
+                "{} not modified, {} records seen",
+                url,
+                seen
+            );
+        }
+        txn.commit().await?;
+        Ok(count)
+    }
+
+    /// COPY into a staging table, then merge it into astroturfers_x
+    /// and the history table. The quarantined rows go in the same
+    /// transaction, on the connection of the COPY.
+    async fn copy_merge(
+        self,
+        db: &DatabaseConnection,
+    ) -> anyhow::Result<u32> {
+        let mut tx = db
+            .get_postgres_connection_pool()
+            .begin()
+            .await?;
+        for (run_id, at, rejected) in &self.rejected {
+            for insert in quarantine::inserts(
+                DatabaseBackend::Postgres,
+                *run_id,
+                *at,
+                rejected,
+            ) {
+                let insert = insert
+                    .into_query()
+                    .to_string(PostgresQueryBuilder);
+                sqlx::query(&insert)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+        sqlx::query(&format!(
+            "CREATE TEMP TABLE {} (LIKE {} INCLUDING DEFAULTS, \
+             seen_at timestamptz NOT NULL, ord bigint NOT NULL) \
+             ON COMMIT DROP",
+            STAGING,
+            qualified(to_db::Entity)
+        ))
+        .execute(&mut *tx)
+        .await?;
+
+        let columns: Vec<&str> = COLUMNS
+            .iter()
+            .map(|column| column.as_str())
+            .chain(["seen_at", "ord"])
+            .collect();
+        let mut copy = tx
+            .copy_in_raw(&format!(
+                "COPY {} ({}) FROM STDIN (FORMAT csv)",
+                STAGING,
+                columns.join(", ")
+            ))
+            .await?;
+        let rows: Vec<_> =
+            self.rows.iter().enumerate().collect();
+        for chunk in rows.chunks(COPY_CHUNK_ROWS) {
+            let mut csv = String::new();
+            for (ord, (seen_at, model)) in chunk {
+                csv_line(
+                    &mut csv,
+                    copy_fields(*ord, *seen_at, model),
+                );
+            }
+            copy.send(csv.into_bytes()).await?;
+        }
+        copy.finish().await?;
+
+        let on_conflict = if self.reparse {
+            to_db::reparse_on_conflict()
+        } else {
+            to_db::on_conflict()
+        };
+        let count = sqlx::query(&merge_rows(on_conflict)?)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        if !self.reparse {
+            sqlx::query(&merge_history()?)
+                .execute(&mut *tx)
+                .await?;
+        }
+        for touch in self.touch_quarantined() {
+            let touch = touch
+                .into_query()
+                .to_string(PostgresQueryBuilder);
+            sqlx::query(&touch).execute(&mut *tx).await?;
+        }
+        for (run_id, url) in &self.touched {
+            let touch = to_db::touch_query(*run_id, url)
+                .into_query()
+                .to_string(PostgresQueryBuilder);
+            let seen = sqlx::query(&touch)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+            log::info!(
+                "{} not modified, {} records seen",
+                url,
+                seen
+            );
+        }
+        tx.commit().await?;
+        Ok(count as u32)
+    }
+}
+
+/// `"schema"."table"` of `entity`
+fn qualified(entity: impl EntityName) -> String {
+    match entity.schema_name() {
+        Some(schema) => {
+            format!(
+                "\"{}\".\"{}\"",
+                schema,
+                entity.table_name()
+            )
+        }
+        None => format!("\"{}\"", entity.table_name()),
+    }
+}
+
+/// the last staged row of every account upserted with `on_conflict`
+fn merge_rows(
+    on_conflict: OnConflict,
+) -> anyhow::Result<String> {
+    let last = Query::select()
+        .distinct_on([Column::UserId])
+        .columns(COLUMNS)
+        .from(Alias::new(STAGING))
+        .order_by(Column::UserId, Order::Asc)
+        .order_by(Alias::new("ord"), Order::Desc)
+        .to_owned();
+    Ok(Query::insert()
+        .into_table(to_db::Entity.table_ref())
+        .columns(COLUMNS)
+        .select_from(last)?
+        .on_conflict(on_conflict)
+        .to_string(PostgresQueryBuilder))
+}
+
+/// the staged identities recorded like `history::record`, first seen
+/// at their earliest and last seen at their last row
+fn merge_history() -> anyhow::Result<String> {
+    let identity =
+        [Column::UserId, Column::Name, Column::Handle];
+    let last = Query::select()
+        .distinct_on(identity)
+        .columns(identity)
+        .columns([Column::Avatar, Column::ChangedNameCount])
+        .expr(Expr::cust(
+            "min(seen_at) OVER \
+             (PARTITION BY user_id, name, handle)",
+        ))
+        .expr(Expr::col(Alias::new("seen_at")))
+        .from(Alias::new(STAGING))
+        .order_by_columns(identity.map(|c| (c, Order::Asc)))
+        .order_by(Alias::new("ord"), Order::Desc)
+        .to_owned();
+    Ok(Query::insert()
+        .into_table(history::Entity.table_ref())
+        .columns([
+            history::Column::UserId,
+            history::Column::Name,
+            history::Column::Handle,
+            history::Column::Avatar,
+            history::Column::ChangedNameCount,
+            history::Column::FirstSeen,
+            history::Column::LastSeen,
+        ])
+        .select_from(last)?
+        .on_conflict(history::on_conflict())
+        .to_string(PostgresQueryBuilder))
+}
+
+/// a staged row, `COLUMNS` then `seen_at` and `ord`
+fn copy_fields(
+    ord: usize,
+    seen_at: DateTimeUtc,
+    model: &Model,
+) -> impl Iterator<Item = Option<String>> {
+    fields(model).into_iter().chain([
+        Some(seen_at.to_rfc3339()),
+        Some(ord.to_string()),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::crawl_run::{self, Tally};
+    use super::super::db::test_db::TestDb;
+    use super::super::health::RunHealth;
+    use super::*;
+    use sea_orm::{PaginatorTrait, QueryOrder};
+
+    #[test]
+    fn copy_line() {
+        let model = Model {
+            name: "say \"hi\", ok".to_string(),
+            avatar: String::new(),
+            register_time: chrono::NaiveDate::from_ymd_opt(
+                2024, 9, 2,
+            ),
+            changed_name_count: 3,
+            first_seen_run: Some(7),
+            last_seen_run: Some(7),
+            ..Model::test_account("1830540823630675969")
+        };
+        let seen_at =
+            chrono::DateTime::from_timestamp(0, 0)
+                .expect("epoch");
+        let mut csv = String::new();
+        csv_line(
+            &mut csv,
+            copy_fields(42, seen_at, &model),
+        );
+        assert_eq!(
+            csv,
+            "\"1830540823630675969\",\"say \"\"hi\"\", ok\",\
+             \"ynhu434128\",\"https://x.com/ynhu434128\",\"\",\
+             \"2024-09-02\",\"\",\"\",\"3\",\"7\",\"7\",\
+             \"https://example.com/\",,,,\
+             \"1970-01-01T00:00:00+00:00\",\"42\"\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn quarantined_account_stays_listed()
+    -> anyhow::Result<()> {
+        let test = TestDb::new().await?;
+        let db = &test.db;
+        let kept = Model::test_account("1");
+        let quarantined = Model::test_account("2");
+        let page = |run_id, models| PageRows {
+            url: "https://example.com/".to_string(),
+            run_id,
+            seen_at: chrono::Utc::now(),
+            models,
+            validators: None,
+        };
+
+        let first = crawl_run::start(db).await?;
+        let mut writer = BulkWriter::new(db);
+        writer.push(page(
+            first.id,
+            vec![kept.clone(), quarantined.clone()],
+        ));
+        writer.flush().await?;
+
+        let run = crawl_run::start(db).await?;
+        let broken = Model {
+            avatar: "javascript:alert(1)".to_string(),
+            ..quarantined
+        };
+        writer.push(page(run.id, vec![kept, broken]));
+        assert_eq!(writer.flush().await?, 1);
+        let tally = Tally {
+            pages_attempted: 1,
+            pages_succeeded: 1,
+            pages_failed: 0,
+            records_written: 1,
+        };
+        let run = crawl_run::finish(
+            db,
+            run.id,
+            tally,
+            &RunHealth::default(),
+            None,
+        )
+        .await?;
+        assert!(run.is_complete());
+        assert_eq!(
+            to_db::tombstone_unseen(db, &run).await?,
+            0
+        );
+
+        let stored = to_db::Entity::find_by_id("2")
+            .one(db)
+            .await?
+            .expect("stored account");
+        assert_eq!(stored.removed_at, None);
+        assert_eq!(stored.last_seen_run, Some(run.id));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reparse_does_not_quarantine_again()
+    -> anyhow::Result<()> {
+        let test = TestDb::new().await?;
+        let db = &test.db;
+        let run = crawl_run::start(db).await?;
+        let page = PageRows {
+            url: "https://example.com/".to_string(),
+            run_id: run.id,
+            seen_at: chrono::Utc::now(),
+            models: vec![Model {
+                avatar: "javascript:alert(1)".to_string(),
+                ..Model::test_account("1")
+            }],
+            validators: None,
+        };
+        let mut writer = BulkWriter::new(db);
+        writer.push(page.clone());
+        writer.flush().await?;
+        // the snapshot of the page is parsed again, twice
+        let mut writer = BulkWriter::new(db).reparse();
+        for _ in 0..2 {
+            writer.push(page.clone());
+            writer.flush().await?;
+        }
+        assert_eq!(
+            quarantine::Entity::find().count(db).await?,
+            1
+        );
+        Ok(())
+    }
+
+    /// two runs of pages through a writer that COPYs batches of
+    /// `copy_min_rows` rows or more, their ids
+    async fn two_runs(
+        db: &DatabaseConnection,
+        copy_min_rows: usize,
+    ) -> anyhow::Result<(i64, i64)> {
+        let at = |secs| {
+            chrono::DateTime::from_timestamp(secs, 0)
+                .expect("a valid time")
+        };
+        let mut writer = BulkWriter::new(db);
+        writer.copy_min_rows = copy_min_rows;
+        let first = crawl_run::start(db).await?;
+        writer.push(PageRows {
+            url: "https://example.com/".to_string(),
+            run_id: first.id,
+            seen_at: at(1_700_000_000),
+            models: vec![
+                Model::test_account("1"),
+                Model::test_account("2"),
+                Model {
+                    handle: "has space".to_string(),
+                    ..Model::test_account("3")
+                },
+            ],
+            validators: None,
+        });
+        writer.flush().await?;
+        let second = crawl_run::start(db).await?;
+        writer.push(PageRows {
+            url: "https://example.com/".to_string(),
+            run_id: second.id,
+            seen_at: at(1_700_086_400),
+            models: vec![
+                Model {
+                    name: "改名".to_string(),
+                    ..Model::test_account("1")
+                },
+                Model::test_account("4"),
+            ],
+            validators: None,
+        });
+        writer.flush().await?;
+        Ok((first.id, second.id))
+    }
+
+    /// the rows, identities and quarantine of `db`, in a stable order
+    async fn written(
+        db: &DatabaseConnection,
+    ) -> anyhow::Result<(
+        Vec<Model>,
+        Vec<history::Model>,
+        Vec<quarantine::Model>,
+    )> {
+        let rows = to_db::Entity::find()
+            .order_by_asc(Column::UserId)
+            .all(db)
+            .await?;
+        // ids of the history follow the order it was written in
+        let mut identities =
+            history::Entity::find().all(db).await?;
+        for identity in &mut identities {
+            identity.id = 0;
+        }
+        identities.sort_by(|a, b| {
+            (&a.user_id, &a.name)
+                .cmp(&(&b.user_id, &b.name))
+        });
+        let quarantined = quarantine::Entity::find()
+            .order_by_asc(quarantine::Column::Id)
+            .all(db)
+            .await?;
+        Ok((rows, identities, quarantined))
+    }
+
+    /// Postgres only, with `TEST_DATABASE_URL`
+    #[tokio::test]
+    async fn copy_merges_like_insert() -> anyhow::Result<()>
+    {
+        let copied = TestDb::new().await?;
+        let db = &copied.db;
+        if db.get_database_backend()
+            != DatabaseBackend::Postgres
+        {
+            return Ok(());
+        }
+        let inserted = TestDb::new().await?;
+        two_runs(&inserted.db, usize::MAX).await?;
+        let (first, second) = two_runs(db, 1).await?;
+
+        let copy = written(db).await?;
+        assert_eq!(copy, written(&inserted.db).await?);
+        let (rows, identities, quarantined) = copy;
+        // account 1 is updated in place by the second run
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].name, "改名");
+        assert_eq!(
+            (rows[0].first_seen_run, rows[0].last_seen_run),
+            (Some(first), Some(second))
+        );
+        assert_eq!(identities.len(), 4);
+        assert_eq!(quarantined.len(), 1);
+
+        // a merge that fails takes the quarantined rows with it
+        db.execute_unprepared(&format!(
+            "DROP TABLE {}",
+            qualified(history::Entity)
+        ))
+        .await?;
+        assert!(two_runs(db, 1).await.is_err());
+        assert_eq!(
+            quarantine::Entity::find().count(db).await?,
+            1
+        );
+        Ok(())
+    }
+}
@@ -3,5 +3,20 @@ from astroturfers_x;
 select *
 from astroturfers_x
 -- order by changed_name_count desc
-order by register_time::date
-;
\ No newline at end of file
+order by register_time
+;
+select *
+from crawl_runs
+order by id desc;
+-- suspect pages, their html is in page_snapshots
+select *
+from page_health
+order by run_id desc, url;
+-- rows that failed validation
+select *
+from quarantine
+order by id desc;
+-- register_time off the creation time of the snowflake id
+select user_id, register_time, id_created_at
+from astroturfers_x
+where abs(register_time - id_created_at::date) > 1;
@@ -1,233 +1,865 @@
 //! crawl astroturfers from X_based_china
-use super::to_db::Model;
+use super::bulk::{BulkWriter, PageRows};
+use super::crawl_run::{self, Tally};
+use super::db;
+use super::health::{
+    self, PageHealth, RunHealth, Thresholds,
+};
+use super::http_cache;
+use super::normalize;
+use super::snapshot;
+use super::to_db::{self, Model};
+use crate::extract::{
+    Extract, ExtractError, Extractor, Page, Record,
+};
+use anyhow::Context;
+use futures::TryStreamExt;
 use scraper::Selector;
+use sea_orm::DatabaseConnection;
+use std::sync::Arc;
+use utils::config::env::{CommitMode, ENV_SETTINGS};
+use utils::tools::fetch::{Conditional, FETCHER};
 
-struct XCrawl;
+const DEFAULT_BASE_URL: &str =
+    "https://pluto0x0.github.io/X_based_china";
+/// safety net for pagination discovery
+const DEFAULT_MAX_PAGE: u32 = 200;
+
+/// pages of the site, the page count is discovered at runtime
+struct XCrawl {
+    base_url: String,
+    max_page: u32,
+    /// parses the pages in place of the derive, see `load_spec`
+    spec: Option<Arc<Extractor>>,
+}
 impl XCrawl {
-    fn url_1(page: u32) -> anyhow::Result<String> {
-        let base_url =
-            "https://pluto0x0.github.io/X_based_china";
+    fn new(base_url: &str, max_page: u32) -> Self {
+        Self {
+            base_url: base_url
+                .trim_end_matches('/')
+                .to_string(),
+            max_page,
+            spec: None,
+        }
+    }
+    /// base url, page limit and spec from `X_BASE_URL` /
+    /// `X_MAX_PAGE` / `X_EXTRACTOR_SPEC`
+    fn from_config() -> anyhow::Result<Self> {
+        let settings = &ENV_SETTINGS;
+        Ok(Self {
+            spec: load_spec()?,
+            ..Self::new(
+                settings
+                    .x_base_url
+                    .as_deref()
+                    .unwrap_or(DEFAULT_BASE_URL),
+                settings
+                    .x_max_page
+                    .unwrap_or(DEFAULT_MAX_PAGE),
+            )
+        })
+    }
+    fn url_1(&self, page: u32) -> anyhow::Result<String> {
         let url = if page == 1 {
-            format!("{}/", base_url)
+            format!("{}/", self.base_url)
         } else {
-            format!("{}/page{}.html", base_url, page)
+            format!("{}/page{}.html", self.base_url, page)
         };
         Ok(url)
     }
-    fn all_url() -> anyhow::Result<Vec<String>> {
-        let max_page = 48;
+    /// page number of a pagination href, `./` and `index.html` are page 1
+    fn page_number(href: &str) -> Option<u32> {
+        let path = href.split(['?', '#']).next()?;
+        let file = path.rsplit('/').next()?;
+        if file.is_empty() || file == "index.html" {
+            return Some(1);
+        }
+        file.strip_prefix("page")?
+            .strip_suffix(".html")?
+            .parse()
+            .ok()
+    }
+    /// the highest page linked from `html`, usually the `last` link
+    fn last_linked_page(&self, html: &str) -> Option<u32> {
+        let link_selector =
+            Selector::parse("a[href]").unwrap();
+        scraper::Html::parse_document(html)
+            .select(&link_selector)
+            .filter_map(|a| a.value().attr("href"))
+            .filter(|href| {
+                !href.contains("://")
+                    || href.starts_with(&self.base_url)
+            })
+            .filter_map(Self::page_number)
+            .max()
+    }
+    fn has_cards(html: &str) -> bool {
+        let card_selector =
+            Selector::parse("article.user-card").unwrap();
+        scraper::Html::parse_document(html)
+            .select(&card_selector)
+            .next()
+            .is_some()
+    }
+    /// body of a page, `None` when the page does not exist
+    async fn fetch(
+        &self,
+        page: u32,
+    ) -> anyhow::Result<Option<String>> {
+        let res = FETCHER.get(&self.url_1(page)?).await?;
+        if res.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(res.error_for_status()?.text().await?))
+    }
+    /// Follow the pagination links, fall back to probing page by page
+    /// until a 404 or an empty page when the site has no pagination.
+    async fn page_count(&self) -> anyhow::Result<u32> {
+        let mut html =
+            self.fetch(1).await?.ok_or_else(|| {
+                anyhow::anyhow!(
+                    "{} not found",
+                    self.base_url
+                )
+            })?;
+        let mut last = 1;
+        while let Some(next) = self
+            .last_linked_page(&html)
+            .filter(|&page| page > last)
+        {
+            if next >= self.max_page {
+                return Ok(self.max_page);
+            }
+            match self.fetch(next).await? {
+                Some(page_html) => {
+                    last = next;
+                    html = page_html;
+                }
+                None => break,
+            }
+        }
+        if last > 1 {
+            return Ok(last);
+        }
+        while last < self.max_page {
+            match self.fetch(last + 1).await? {
+                Some(page_html)
+                    if Self::has_cards(&page_html) =>
+                {
+                    last += 1
+                }
+                _ => break,
+            }
+        }
+        Ok(last)
+    }
+    /// The site goes on after `max_page`, probed only when discovery
+    /// stopped at the limit. A site of exactly `max_page` pages is not.
+    async fn is_capped(
+        &self,
+        pages: u32,
+    ) -> anyhow::Result<bool> {
+        if pages < self.max_page {
+            return Ok(false);
+        }
+        let next = self.fetch(self.max_page + 1).await?;
+        Ok(next.is_some_and(|html| Self::has_cards(&html)))
+    }
+    async fn all_url(&self) -> anyhow::Result<Vec<String>> {
+        let max_page = self.page_count().await?;
+        log::info!("discovered {} pages", max_page);
         let urls = (1..=max_page)
-            .map(Self::url_1)
+            .map(|page| self.url_1(page))
             .collect::<anyhow::Result<Vec<_>>>()?;
         Ok(urls)
     }
 }
 
+/// `X_EXTRACTOR_SPEC` replaces the `#[derive(Extract)]` of `Model`, to
+/// follow markup changes without a rebuild. It is loaded before a run
+/// starts, a bad spec fails it before anything is fetched.
+fn load_spec() -> anyhow::Result<Option<Arc<Extractor>>> {
+    ENV_SETTINGS
+        .x_extractor_spec
+        .as_ref()
+        .map(|path| {
+            Extractor::from_file(path)
+                .context("invalid X_EXTRACTOR_SPEC")
+                .map(Arc::new)
+        })
+        .transpose()
+}
+
 struct ParseHtml {
     url: String,
-    user_card_selector: Selector,
-    name_selector: Selector,
-    handle_selector: Selector,
-    id_selector: Selector,
-    profile_url_selector: Selector,
-    avatar_selector: Selector,
-    meta_selector: Selector,
+    /// the derive of `Model` when `None`
+    spec: Option<Arc<Extractor>>,
 }
 impl ParseHtml {
     fn new(url: String) -> Self {
-        Self {
-            url,
-            user_card_selector: Selector::parse(
-                "article.user-card",
-            )
-                .unwrap(),
-            name_selector: Selector::parse("h2.user-name")
-                .unwrap(),
-            handle_selector: Selector::parse(
-                "div.user-handle",
-            )
-                .unwrap(),
-            id_selector: Selector::parse("div.user-id")
-                .unwrap(),
-            profile_url_selector: Selector::parse(
-                ".user-avatar-wrap a",
-            )
-                .unwrap(),
-            avatar_selector: Selector::parse(
-                "img.user-avatar",
-            )
-                .unwrap(),
-            meta_selector: Selector::parse(
-                "div.user-meta span",
-            )
-                .unwrap(),
-        }
+        Self { url, spec: None }
+    }
+    /// parse by `spec` instead of the derive
+    fn with_spec(
+        mut self,
+        spec: Option<Arc<Extractor>>,
+    ) -> Self {
+        self.spec = spec;
+        self
+    }
+    async fn fetch(&self) -> anyhow::Result<String> {
+        FETCHER.get_text(&self.url).await
     }
     async fn parse_html(
         &self,
     ) -> anyhow::Result<Vec<Model>> {
-        let client = reqwest::Client::new();
-        let res = client.get(&self.url).send().await?;
-        let doc = scraper::Html::parse_document(
-            &res.text().await?,
-        );
-
-        let mut astroturfers_list = Vec::new();
-
-        for element in doc.select(&self.user_card_selector)
-        {
-            let name = element
-                .select(&self.name_selector)
-                .next()
-                .map(|e| {
-                    e.text()
-                        .collect::<String>()
-                        .trim()
-                        .to_string()
-                })
-                .unwrap_or_default();
-
-            let handle = element
-                .select(&self.handle_selector)
-                .next()
-                .map(|e| {
-                    e.text()
-                        .collect::<String>()
-                        .trim()
-                        .to_string()
-                })
-                .unwrap_or_default();
-
-            let id = element
-                .select(&self.id_selector)
-                .next()
-                .map(|e| {
-                    e.text()
-                        .collect::<String>()
-                        .trim()
-                        .replace("ID: ", "")
-                })
-                .unwrap_or_default();
-
-            let profile_url = element
-                .select(&self.profile_url_selector)
-                .next()
-                .and_then(|e| e.value().attr("href"))
-                .map(String::from)
-                .unwrap_or_default();
-
-            let avatar = element
-                .select(&self.avatar_selector)
-                .next()
-                .and_then(|e| e.value().attr("src"))
-                .map(String::from)
-                .unwrap_or_default();
-
-            let meta: Vec<String> = element
-                .select(&self.meta_selector)
-                .map(|e| {
-                    e.text()
-                        .collect::<String>()
-                        .trim()
-                        .to_string()
-                })
-                .collect();
-
-            let mut register_time = String::new();
-            let mut changed_name_count = 0;
-
-            for item in meta {
-                if item.starts_with("注册：") {
-                    register_time =
-                        item.replace("注册：", "");
-                } else if item.starts_with("改名次数：")
-                {
-                    changed_name_count = item
-                        .replace("改名次数：", "")
-                        .parse()
-                        .unwrap_or(0);
-                }
+        Ok(self
+            .parse_document(&self.fetch().await?)
+            .records)
+    }
+    /// cards that fail to extract are logged and left in `errors`
+    fn parse_document(&self, html: &str) -> Page<Model> {
+        self.tidy(match &self.spec {
+            Some(extractor) => {
+                self.parse_with(extractor, html)
             }
-
-            let astroturfers = Model {
-                name,
-                handle,
-                user_id: id,
-                profile_url,
-                avatar,
-                register_time,
-                changed_name_count,
+            None => Model::extract_page(html),
+        })
+    }
+    /// log the errors, then canonicalize the records of this page
+    fn tidy(&self, mut page: Page<Model>) -> Page<Model> {
+        for e in &page.errors {
+            log::warn!(
+                "skip a card of {}: {}",
+                self.url,
+                e
+            );
+        }
+        for model in &mut page.records {
+            model.page_url = self.url.clone();
+            normalize::normalize(model);
+        }
+        page
+    }
+    fn parse_with(
+        &self,
+        extractor: &Extractor,
+        html: &str,
+    ) -> Page<Model> {
+        let mut page = Page::default();
+        for mut record in extractor.extract(html) {
+            page.unknown_meta
+                .append(&mut record.unknown_meta);
+            match Self::to_model(record) {
+                Ok(model) => page.records.push(model),
+                Err(e) => page.errors.push(e),
+            }
+        }
+        page
+    }
+    fn to_model(
+        mut record: Record,
+    ) -> Result<Model, ExtractError> {
+        let invalid = |field, value: String, reason| {
+            ExtractError::Invalid {
+                field,
+                value,
+                reason,
+            }
+        };
+        let register_time =
+            match record.take("register_time") {
+                raw if raw.is_empty() => None,
+                raw => Some(
+                    to_db::register_date(&raw).map_err(
+                        |reason| {
+                            invalid(
+                                "register_time",
+                                raw,
+                                reason,
+                            )
+                        },
+                    )?,
+                ),
+            };
+        let changed_name_count =
+            match record.take("changed_name_count") {
+                raw if raw.is_empty() => 0,
+                raw => raw.parse().map_err(
+                    |e: std::num::ParseIntError| {
+                        invalid(
+                            "changed_name_count",
+                            raw,
+                            e.to_string(),
+                        )
+                    },
+                )?,
             };
+        Ok(Model {
+            user_id: required(&mut record, "user_id")?,
+            name: required(&mut record, "name")?,
+            handle: required(&mut record, "handle")?,
+            profile_url: record.take("profile_url"),
+            avatar: record.take("avatar"),
+            register_time,
+            region: record.take("region"),
+            source: record.take("source"),
+            changed_name_count,
+            first_seen_run: None,
+            last_seen_run: None,
+            page_url: String::new(),
+            removed_at: None,
+            id_created_at: None,
+            last_seen_at: None,
+        })
+    }
+}
 
-            astroturfers_list.push(astroturfers);
+/// `field` of a spec record, empty counts as missing
+fn required(
+    record: &mut Record,
+    field: &'static str,
+) -> Result<String, ExtractError> {
+    match record.take(field) {
+        value if value.is_empty() => {
+            Err(ExtractError::Missing {
+                field,
+                source: "X_EXTRACTOR_SPEC",
+            })
         }
-
-        Ok(astroturfers_list)
+        value => Ok(value),
     }
 }
-struct EndToDB;
+/// the runs of `crawl_x crawl` and `crawl_x reparse`
+pub struct EndToDB;
 impl EndToDB {
+    async fn connect() -> anyhow::Result<DatabaseConnection>
+    {
+        db::connect(ENV_SETTINGS.db_url()?).await
+    }
     /// End of the crawl task
-    async fn end() -> anyhow::Result<()> {
-        dotenvy::dotenv().ok();
-        let database_url = std::env::var("PG_DB").expect("PG_DB must be set");
-        let db = sea_orm::Database::connect(&database_url).await?;
-
-        let urls = XCrawl::all_url()?;
+    pub async fn end() -> anyhow::Result<()> {
+        let crawl = XCrawl::from_config()?;
+        let db = Self::connect().await?;
+        Self::crawl(&db, &crawl).await
+    }
+    /// crawl every page of `crawl` into `db` as one crawl run
+    async fn crawl(
+        db: &DatabaseConnection,
+        crawl: &XCrawl,
+    ) -> anyhow::Result<()> {
+        let urls = crawl.all_url().await?;
+        // more pages may hide behind the limit, the list is not complete
+        let capped =
+            crawl.is_capped(urls.len() as u32).await?;
+        let run = crawl_run::start(db).await?;
+        let mode =
+            ENV_SETTINGS.x_commit_mode.unwrap_or_default();
+        let mut writer = match mode {
+            CommitMode::Streaming => BulkWriter::new(db),
+            // the pages wait in memory for the end of the run
+            CommitMode::Atomic => {
+                BulkWriter::new(db).unbatched()
+            }
+        };
+        let mut health = RunHealth::default();
+        let mut tally = Tally {
+            pages_attempted: urls.len() as u32,
+            ..Default::default()
+        };
 
         use futures::stream::{self, StreamExt};
 
         // Create a stream that processes pages concurrently
+        let cache = http_cache::load_all(db).await?;
         let mut stream = stream::iter(urls)
             .map(|url| {
-                let parser = ParseHtml::new(url);
-                async move { parser.parse_html().await }
+                let validators = cache
+                    .get(&url)
+                    .cloned()
+                    .unwrap_or_default();
+                let parser = ParseHtml::new(url)
+                    .with_spec(crawl.spec.clone());
+                async move {
+                    let fetched = FETCHER
+                        .get_conditional(
+                            &parser.url,
+                            &validators,
+                        )
+                        .await;
+                    (parser, fetched)
+                }
             })
             .buffer_unordered(5); // Adjust concurrency limit as needed
 
-        while let Some(result) = stream.next().await {
-            match result {
-                Ok(models) => {
-                    if !models.is_empty() {
-                        println!("Inserting batch of {} records...", models.len());
-                        super::to_db::save_to_db(&db, models).await?;
+        while let Some((parser, fetched)) =
+            stream.next().await
+        {
+            let saved = match fetched {
+                Ok(fetched) => {
+                    Self::handle_page(
+                        db,
+                        run.id,
+                        &parser,
+                        fetched,
+                        &mut health,
+                    )
+                    .await
+                }
+                Err(e) => Err(e),
+            };
+            match saved {
+                Ok(None) => {
+                    writer.touch(run.id, parser.url)
+                }
+                Ok(Some(rows)) => {
+                    if writer.push(rows) {
+                        Self::flush(
+                            &mut writer,
+                            &mut tally,
+                        )
+                        .await;
                     }
                 }
                 Err(e) => {
-                    eprintln!("Failed to parse page: {:?}", e);
+                    tally.pages_failed += 1;
+                    log::error!(
+                        "Failed to crawl {}: {:?}",
+                        parser.url,
+                        e
+                    );
                 }
             }
         }
+        let drift = health.drift(&Thresholds::from_env());
+        let veto = match mode {
+            CommitMode::Streaming => None,
+            CommitMode::Atomic => tally.veto(
+                drift.as_deref(),
+                ENV_SETTINGS
+                    .x_atomic_max_failed_pages
+                    .unwrap_or(0),
+            ),
+        };
+        match &veto {
+            None => {
+                Self::flush(&mut writer, &mut tally).await
+            }
+            Some(veto) => {
+                let pages = writer.discard();
+                log::error!(
+                    "discard {} pages of run {}: {}",
+                    pages,
+                    run.id,
+                    veto
+                );
+            }
+        }
+        let run = crawl_run::finish(
+            db, run.id, tally, &health, drift,
+        )
+        .await?;
+        log::info!("crawl run finished: {:?}", run);
+        if let Some(veto) = veto {
+            anyhow::bail!(
+                "run {} committed nothing: {}",
+                run.id,
+                veto
+            );
+        }
+        if let Some(drift) = &run.drift {
+            // rows of the healthy pages stay, nothing is tombstoned
+            anyhow::bail!(
+                "parser drift in run {}: {}, see page_health",
+                run.id,
+                drift
+            );
+        }
+        if capped {
+            log::warn!(
+                "stopped at X_MAX_PAGE={}, tombstone nothing",
+                crawl.max_page
+            );
+        } else {
+            let removed =
+                to_db::tombstone_unseen(db, &run).await?;
+            log::info!(
+                "{} accounts left the list",
+                removed
+            );
+        }
+        for row in
+            to_db::register_mismatches(db, run.id).await?
+        {
+            log::warn!(
+                "{} registered {:?} but its id was minted {:?}",
+                row.user_id,
+                row.register_time,
+                row.id_created_at
+            );
+        }
+        Ok(())
+    }
+    /// write the pages `writer` holds, they all succeed or fail
+    async fn flush(
+        writer: &mut BulkWriter<'_>,
+        tally: &mut Tally,
+    ) {
+        let pages = writer.pending_pages();
+        match writer.flush().await {
+            Ok(count) => {
+                tally.pages_succeeded += pages;
+                tally.records_written += count;
+            }
+            Err(e) => {
+                tally.pages_failed += pages;
+                log::error!(
+                    "Failed to write {} pages: {:?}",
+                    pages,
+                    e
+                );
+            }
+        }
+    }
+    /// rows of a fetched page to write, `None` when it is unchanged and
+    /// only its rows are seen
+    async fn handle_page(
+        db: &DatabaseConnection,
+        run_id: i64,
+        parser: &ParseHtml,
+        fetched: Conditional,
+        health: &mut RunHealth,
+    ) -> anyhow::Result<Option<PageRows>> {
+        match fetched {
+            Conditional::NotModified => Ok(None),
+            Conditional::Modified { body, validators } => {
+                let rows = Self::store_page(
+                    db, run_id, parser, &body, health,
+                )
+                .await?;
+                Ok(Some(PageRows {
+                    validators: Some(validators),
+                    ..rows
+                }))
+            }
+        }
+    }
+    /// archive the page, check its health, then parse the rows to
+    /// write
+    async fn store_page(
+        db: &DatabaseConnection,
+        run_id: i64,
+        parser: &ParseHtml,
+        html: &str,
+        health: &mut RunHealth,
+    ) -> anyhow::Result<PageRows> {
+        let snapshot = snapshot::archive(
+            db,
+            run_id,
+            &parser.url,
+            html,
+        )
+        .await?;
+        let page = parser.parse_document(html);
+        let page_health = PageHealth::of(&page);
+        health.add(&page_health);
+        if page_health.is_suspect() {
+            log::warn!(
+                "suspect page {}, snapshot {}: {:?}",
+                parser.url,
+                snapshot.id,
+                page_health
+            );
+            health::record(
+                db,
+                run_id,
+                &parser.url,
+                snapshot.id,
+                &page_health,
+            )
+            .await?;
+        }
+        log::info!(
+            "Queueing {} records from {}",
+            page.records.len(),
+            parser.url
+        );
+        Ok(PageRows {
+            url: parser.url.clone(),
+            run_id,
+            seen_at: snapshot.fetched_at,
+            models: page.records,
+            validators: None,
+        })
+    }
+    /// Rebuild the rows from the newest snapshot of every page without
+    /// any network access. Stored rows only get their parsed columns
+    /// rewritten, their lineage, tombstone and history stay. Rows new
+    /// to the table keep the run and time of the fetch. Everything is
+    /// written at once, through COPY when it is big. On prod it needs
+    /// `confirmed`, see `guard_destructive`.
+    pub async fn reparse(
+        confirmed: bool,
+    ) -> anyhow::Result<()> {
+        ENV_SETTINGS
+            .guard_destructive("reparse", confirmed)?;
+        let spec = load_spec()?;
+        let db = Self::connect().await?;
+        Self::reparse_into(&db, spec).await?;
         Ok(())
     }
+    /// `reparse` into `db` by `spec`, the count of rows written
+    async fn reparse_into(
+        db: &DatabaseConnection,
+        spec: Option<Arc<Extractor>>,
+    ) -> anyhow::Result<u32> {
+        let mut writer =
+            BulkWriter::new(db).unbatched().reparse();
+        let mut rows = Box::pin(
+            snapshot::latest_per_url().stream(db).await?,
+        );
+        while let Some(snapshot) = rows.try_next().await? {
+            let parser =
+                ParseHtml::new(snapshot.url.clone())
+                    .with_spec(spec.clone());
+            let page =
+                parser.parse_document(&snapshot.html()?);
+            let page_health = PageHealth::of(&page);
+            if page_health.is_suspect() {
+                log::warn!(
+                    "suspect snapshot {}: {:?}",
+                    snapshot.id,
+                    page_health
+                );
+            }
+            log::info!(
+                "Re-parsed {} records from {}",
+                page.records.len(),
+                snapshot.url
+            );
+            writer.push(PageRows {
+                url: snapshot.url,
+                run_id: snapshot.run_id,
+                seen_at: snapshot.fetched_at,
+                models: page.records,
+                validators: None,
+            });
+        }
+        // the stream holds a connection until it is dropped
+        drop(rows);
+        let count = writer.flush().await?;
+        log::info!("Re-parsed {} records in total", count);
+        Ok(count)
+    }
 }
 
 #[cfg(test)]
 mod test {
+    use super::super::db::test_db::TestDb;
+    use super::super::{history, validate};
     use super::*;
-    use rand::prelude::*;
+    use chrono::SubsecRound;
     use scraper::Html;
+    use sea_orm::sea_query::Expr;
+    use sea_orm::{
+        ColumnTrait, EntityTrait, PaginatorTrait,
+        QueryFilter, QueryOrder,
+    };
+    use utils::tools::fixture;
+
+    /// Two pages of the site, synthetic: written by hand after its
+    /// markup, with made-up `etag` and `last-modified` validators.
+    /// Replace them with real ones by recording with
+    /// `FETCH_FIXTURES=record FETCH_FIXTURE_DIR=<this dir>`.
+    const FIXTURES: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/fixtures/x_based_china"
+    );
+
+    /// the recorded site, served locally
+    fn recorded_site() -> anyhow::Result<XCrawl> {
+        let server = fixture::serve(FIXTURES.into())?;
+        let url = reqwest::Url::parse(DEFAULT_BASE_URL)?;
+        let base_url = fixture::replay_url(&server, &url);
+        Ok(XCrawl::new(base_url.as_str(), DEFAULT_MAX_PAGE))
+    }
+
     #[tokio::test]
     async fn end() -> anyhow::Result<()> {
-        EndToDB::end().await
+        let test = TestDb::new().await?;
+        EndToDB::crawl(&test.db, &recorded_site()?).await?;
+
+        let rows =
+            to_db::Entity::find().all(&test.db).await?;
+        assert_eq!(rows.len(), 5);
+        let run = crawl_run::Entity::find()
+            .one(&test.db)
+            .await?
+            .expect("a crawl run");
+        assert!(run.is_complete(), "{:?}", run);
+        Ok(())
     }
 
     #[tokio::test]
-    async fn test_1() {
-        let urls = XCrawl::all_url().unwrap();
-        // 随机获取一个url
-        let mut rng = rand::rng();
-        let url = urls.choose(&mut rng).unwrap().clone();
-
-        let parse = ParseHtml::new(url);
-        let end = parse.parse_html().await.unwrap();
-        for astroturfer in end {
-            println!("{:?}\n", astroturfer);
+    async fn not_modified_pages_are_seen()
+    -> anyhow::Result<()> {
+        let test = TestDb::new().await?;
+        let db = &test.db;
+        let site = recorded_site()?;
+        EndToDB::crawl(db, &site).await?;
+        assert_eq!(
+            http_cache::load_all(db).await?.len(),
+            2
+        );
+
+        // the validators match the fixtures, every page is a 304
+        EndToDB::crawl(db, &site).await?;
+        let run = crawl_run::Entity::find()
+            .order_by_desc(crawl_run::Column::Id)
+            .one(db)
+            .await?
+            .expect("the second run");
+        assert!(run.is_complete(), "{:?}", run);
+        assert_eq!(
+            (run.records_written, run.cards_seen),
+            (0, 0)
+        );
+        // nothing parsed, so nothing archived
+        assert_eq!(
+            snapshot::Entity::find().count(db).await?,
+            2
+        );
+        let rows = to_db::Entity::find().all(db).await?;
+        assert_eq!(rows.len(), 5);
+        for row in rows {
+            assert_eq!(row.last_seen_run, Some(run.id));
+            assert_eq!(row.removed_at, None);
         }
+        Ok(())
     }
-    #[test]
-    fn parse_html() {
-        let html = r#"
+
+    #[tokio::test]
+    async fn capped_run_tombstones_nothing()
+    -> anyhow::Result<()> {
+        let test = TestDb::new().await?;
+        let db = &test.db;
+        let site = recorded_site()?;
+        EndToDB::crawl(db, &site).await?;
+        // page 2 is behind the limit, its accounts did not leave
+        let capped = XCrawl::new(&site.base_url, 1);
+        EndToDB::crawl(db, &capped).await?;
+
+        let run = crawl_run::Entity::find()
+            .order_by_desc(crawl_run::Column::Id)
+            .one(db)
+            .await?
+            .expect("the capped run");
+        assert!(run.is_complete(), "{:?}", run);
+        for row in to_db::Entity::find().all(db).await? {
+            assert_eq!(
+                row.removed_at, None,
+                "{}",
+                row.user_id
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reparse() -> anyhow::Result<()> {
+        let test = TestDb::new().await?;
+        let db = &test.db;
+        EndToDB::crawl(db, &recorded_site()?).await?;
+        // a later run saw every row on unchanged pages, then one
+        // account left the list
+        let later = chrono::Utc::now().trunc_subsecs(0)
+            + chrono::Duration::days(1);
+        to_db::Entity::update_many()
+            .col_expr(
+                to_db::Column::LastSeenRun,
+                Expr::value(Some(99_i64)),
+            )
+            .exec(db)
+            .await?;
+        history::Entity::update_many()
+            .col_expr(
+                history::Column::LastSeen,
+                Expr::value(later),
+            )
+            .exec(db)
+            .await?;
+        let gone = to_db::Entity::find()
+            .one(db)
+            .await?
+            .expect("a row");
+        to_db::Entity::update_many()
+            .col_expr(
+                to_db::Column::RemovedAt,
+                Expr::value(Some(later)),
+            )
+            .filter(to_db::Column::UserId.eq(&gone.user_id))
+            .exec(db)
+            .await?;
+
+        let count = EndToDB::reparse_into(db, None).await?;
+        assert_eq!(count, 5);
+        for row in to_db::Entity::find().all(db).await? {
+            assert_eq!(row.last_seen_run, Some(99));
+            let removed_at = (row.user_id == gone.user_id)
+                .then_some(later);
+            assert_eq!(row.removed_at, removed_at);
+        }
+        for identity in
+            history::Entity::find().all(db).await?
+        {
+            assert_eq!(identity.last_seen, later);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_1() -> anyhow::Result<()> {
+        let urls = recorded_site()?.all_url().await?;
+        // 每个录制的页面都要能解析
+        for url in urls {
+            let parse = ParseHtml::new(url);
+            let end = parse.parse_html().await?;
+            assert!(!end.is_empty(), "{}", parse.url);
+            for astroturfer in end {
+                let problems =
+                    validate::problems(&astroturfer);
+                assert!(
+                    problems.is_empty(),
+                    "{:?}",
+                    problems
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn recorded_page() -> anyhow::Result<()> {
+        let crawl = recorded_site()?;
+        let parse = ParseHtml::new(crawl.url_1(2)?);
+        let records = parse.parse_html().await?;
+        let handles: Vec<&str> = records
+            .iter()
+            .map(|record| record.handle.as_str())
+            .collect();
+        assert_eq!(
+            handles,
+            ["laowang_kansj", "chenxi_0712"]
+        );
+        // twitter.com links are stored as x.com ones
+        assert_eq!(
+            records[1].profile_url,
+            "https://x.com/chenxi_0712"
+        );
+        assert_eq!(records[1].changed_name_count, 3);
+        Ok(())
+    }
+    const CARD_HTML: &str = r#"
     <article class="user-card">
         <div class="user-avatar-wrap">
             <a href="https://twitter.com/ynhu434128" target="_blank" rel="noopener noreferrer">
@@ -249,6 +881,10 @@ mod test {
     </article>
     "#;
 
+    #[test]
+    fn parse_html() {
+        let html = CARD_HTML;
+
         let doc = Html::parse_document(html);
 
         // 头像链接
@@ -296,25 +932,243 @@ mod test {
         }
     }
     #[test]
-    fn all_urls() {
-        let urls = XCrawl::all_url().unwrap();
-        for url in urls {
-            println!("{}", url)
-        }
+    fn parse_meta_fields() {
+        let parse = ParseHtml::new(String::new());
+        let models =
+            parse.parse_document(CARD_HTML).records;
+        assert_eq!(models.len(), 1);
+        let model = &models[0];
+        assert_eq!(model.user_id, "1830540823630675969");
+        assert_eq!(model.handle, "ynhu434128");
+        assert_eq!(
+            model.profile_url,
+            "https://x.com/ynhu434128"
+        );
+        assert!(!model.avatar.contains("_normal"));
+        assert_eq!(
+            model.register_time,
+            chrono::NaiveDate::from_ymd_opt(2024, 9, 2)
+        );
+        assert_eq!(model.region, "China");
+        assert_eq!(model.source, "Web");
+        assert_eq!(model.changed_name_count, 0);
+    }
+    #[test]
+    fn garbage_register_date_is_an_error()
+    -> anyhow::Result<()> {
+        let parse = ParseHtml::new(String::new());
+        let html = CARD_HTML
+            .replace("注册：2024-09-02", "注册：上周");
+        let page = parse.parse_document(&html);
+        assert!(page.records.is_empty());
+        assert!(matches!(
+            page.errors[..],
+            [ExtractError::Invalid {
+                field: "register_time",
+                ..
+            }]
+        ));
+        assert_eq!(PageHealth::of(&page).rejected, 1);
+        let extractor = Extractor::from_toml(
+            include_str!("x_based_china.toml"),
+        )?;
+        assert_eq!(
+            parse.tidy(parse.parse_with(&extractor, &html)),
+            page
+        );
+        Ok(())
+    }
+    #[test]
+    fn bundled_spec_matches_derive() -> anyhow::Result<()> {
+        let parse = ParseHtml::new(String::new());
+        let extractor = Extractor::from_toml(
+            include_str!("x_based_china.toml"),
+        )?;
+        assert_eq!(
+            parse.tidy(
+                parse.parse_with(&extractor, CARD_HTML)
+            ),
+            parse.parse_document(CARD_HTML)
+        );
+        Ok(())
+    }
+    #[test]
+    fn drifted_markup_is_suspect() {
+        let parse = ParseHtml::new(String::new());
+        let drifted = CARD_HTML
+            .replace("user-id", "account-id")
+            .replace("来源", "客户端");
+        let page = parse.parse_document(&drifted);
+        let health = PageHealth::of(&page);
+        assert_eq!((health.cards, health.rejected), (1, 1));
+        assert!(health.unknown_labels.contains("客户端"));
+        assert!(
+            PageHealth::of(
+                &parse.parse_document("<html></html>")
+            )
+            .is_suspect()
+        );
+    }
+
+    #[tokio::test]
+    async fn all_urls() -> anyhow::Result<()> {
+        let crawl = recorded_site()?;
+        let urls = crawl.all_url().await?;
+        assert_eq!(
+            urls,
+            [crawl.url_1(1)?, crawl.url_1(2)?]
+        );
+        Ok(())
     }
 
     #[test]
     fn url() {
-        let url = XCrawl::url_1(1).unwrap();
+        let crawl = XCrawl::new(DEFAULT_BASE_URL, 10);
+        let url = crawl.url_1(1).unwrap();
         assert_eq!(
             url,
             "https://pluto0x0.github.io/X_based_china/"
         );
 
-        let url = XCrawl::url_1(3).unwrap();
+        let url = crawl.url_1(3).unwrap();
         assert_eq!(
             url,
             "https://pluto0x0.github.io/X_based_china/page3.html"
         );
     }
+
+    #[test]
+    fn pagination_links() {
+        assert_eq!(XCrawl::page_number("./"), Some(1));
+        assert_eq!(
+            XCrawl::page_number("page7.html"),
+            Some(7)
+        );
+        assert_eq!(
+            XCrawl::page_number(
+                "/X_based_china/page12.html?x=1"
+            ),
+            Some(12)
+        );
+        assert_eq!(XCrawl::page_number("about.html"), None);
+
+        let html = r#"
+    <nav class="pagination">
+        <a href="./">1</a>
+        <a href="page2.html">下一页</a>
+        <a href="page48.html">末页</a>
+        <a href="https://github.com/page99.html">repo</a>
+    </nav>
+    "#;
+        let crawl = XCrawl::new(DEFAULT_BASE_URL, 100);
+        assert_eq!(crawl.last_linked_page(html), Some(48));
+        assert_eq!(crawl.last_linked_page("<p></p>"), None);
+    }
+
+    /// serve `pages` cards-only pages without pagination links
+    /// every page of `local_mirror`, a card the parser cannot read
+    const MIRROR_PAGE: &str =
+        r#"<article class="user-card"></article>"#;
+
+    async fn local_mirror(pages: u32) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let listener =
+            tokio::net::TcpListener::bind("127.0.0.1:0")
+                .await
+                .unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) =
+                listener.accept().await
+            {
+                let mut buf = [0; 1024];
+                let n = stream
+                    .read(&mut buf)
+                    .await
+                    .unwrap_or(0);
+                let request =
+                    String::from_utf8_lossy(&buf[..n]);
+                let path = request
+                    .split(' ')
+                    .nth(1)
+                    .unwrap_or("/");
+                let page = XCrawl::page_number(path)
+                    .filter(|&page| page <= pages);
+                let res = match page {
+                    Some(_) => {
+                        let body = MIRROR_PAGE;
+                        format!(
+                            "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                            body.len(),
+                            body
+                        )
+                    }
+                    None => "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n".to_string(),
+                };
+                stream.write_all(res.as_bytes()).await.ok();
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn probe_local_mirror() -> anyhow::Result<()> {
+        let base_url = local_mirror(3).await;
+        let urls =
+            XCrawl::new(&base_url, 10).all_url().await?;
+        assert_eq!(urls.len(), 3);
+        assert_eq!(
+            urls[2],
+            format!("{}/page3.html", base_url)
+        );
+
+        let capped = XCrawl::new(&base_url, 2);
+        assert_eq!(capped.all_url().await?.len(), 2);
+        assert!(capped.is_capped(2).await?);
+        // all of the site, nothing behind the limit
+        let exact = XCrawl::new(&base_url, 3);
+        assert_eq!(exact.all_url().await?.len(), 3);
+        assert!(!exact.is_capped(3).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn drifted_page_fails_the_run()
+    -> anyhow::Result<()> {
+        let test = TestDb::new().await?;
+        let db = &test.db;
+        let site = XCrawl::new(&local_mirror(1).await, 10);
+        let err = EndToDB::crawl(db, &site)
+            .await
+            .expect_err("the only card is rejected");
+        assert!(err.to_string().contains("parser drift"));
+
+        let run = crawl_run::Entity::find()
+            .one(db)
+            .await?
+            .expect("a crawl run");
+        assert!(!run.is_complete(), "{:?}", run);
+        assert_eq!(
+            run.drift.as_deref(),
+            Some("1 of 1 cards rejected")
+        );
+        let suspect =
+            health::Entity::find().all(db).await?;
+        assert_eq!(suspect.len(), 1);
+        assert_eq!(suspect[0].run_id, run.id);
+        assert_eq!(suspect[0].url, site.url_1(1)?);
+        assert_eq!(
+            (suspect[0].cards, suspect[0].rejected),
+            (1, 1)
+        );
+        // the html the parser saw, to fix the selectors against
+        let snapshot = snapshot::Entity::find_by_id(
+            suspect[0].snapshot_id,
+        )
+        .one(db)
+        .await?
+        .expect("the snapshot of the page");
+        assert_eq!(snapshot.html()?, MIRROR_PAGE);
+        Ok(())
+    }
 }
@@ -0,0 +1,238 @@
+//! bookkeeping of every crawl run
+use super::health::RunHealth;
+use sea_orm::ActiveValue::{NotSet, Set};
+use sea_orm::entity::prelude::*;
+
+#[derive(
+    Clone, Debug, PartialEq, Eq, DeriveEntityModel,
+)]
+#[sea_orm(table_name = "crawl_runs")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub started_at: DateTimeUtc,
+    /// `None` while running, or when the run died halfway
+    pub finished_at: Option<DateTimeUtc>,
+    pub pages_attempted: u32,
+    pub pages_succeeded: u32,
+    pub pages_failed: u32,
+    pub records_written: u32,
+    /// cards on the pages parsed by the run
+    pub cards_seen: u32,
+    pub cards_rejected: u32,
+    pub empty_pages: u32,
+    /// why the run failed its health checks, see `health::Thresholds`
+    pub drift: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Model {
+    /// Finished healthy with every page crawled, nothing else may
+    /// tombstone. A page answered 304 counts as crawled, it writes no
+    /// rows but sees them all.
+    pub fn is_complete(&self) -> bool {
+        self.finished_at.is_some()
+            && self.drift.is_none()
+            && self.pages_attempted > 0
+            && self.pages_failed == 0
+            && self.pages_succeeded == self.pages_attempted
+    }
+}
+
+/// counters of a run in progress
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Tally {
+    pub pages_attempted: u32,
+    pub pages_succeeded: u32,
+    pub pages_failed: u32,
+    pub records_written: u32,
+}
+
+impl Tally {
+    /// why an atomic run must not commit its rows, `None` when it may
+    pub fn veto(
+        &self,
+        drift: Option<&str>,
+        max_failed_pages: u32,
+    ) -> Option<String> {
+        if let Some(drift) = drift {
+            return Some(format!(
+                "parser drift, {}",
+                drift
+            ));
+        }
+        (self.pages_failed > max_failed_pages).then(|| {
+            format!(
+                "{} of {} pages failed",
+                self.pages_failed, self.pages_attempted
+            )
+        })
+    }
+}
+
+/// open a new run starting now
+pub async fn start<C: ConnectionTrait>(
+    db: &C,
+) -> Result<Model, DbErr> {
+    ActiveModel {
+        id: NotSet,
+        started_at: Set(chrono::Utc::now()),
+        finished_at: Set(None),
+        pages_attempted: Set(0),
+        pages_succeeded: Set(0),
+        pages_failed: Set(0),
+        records_written: Set(0),
+        cards_seen: Set(0),
+        cards_rejected: Set(0),
+        empty_pages: Set(0),
+        drift: Set(None),
+    }
+    .insert(db)
+    .await
+}
+
+/// close run `id` with its final counters, `drift` fails it
+pub async fn finish<C: ConnectionTrait>(
+    db: &C,
+    id: i64,
+    tally: Tally,
+    health: &RunHealth,
+    drift: Option<String>,
+) -> Result<Model, DbErr> {
+    ActiveModel {
+        id: Set(id),
+        started_at: NotSet,
+        finished_at: Set(Some(chrono::Utc::now())),
+        pages_attempted: Set(tally.pages_attempted),
+        pages_succeeded: Set(tally.pages_succeeded),
+        pages_failed: Set(tally.pages_failed),
+        records_written: Set(tally.records_written),
+        cards_seen: Set(health.cards),
+        cards_rejected: Set(health.rejected),
+        empty_pages: Set(health.empty_pages),
+        drift: Set(drift),
+    }
+    .update(db)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::db::test_db::TestDb;
+    use super::*;
+
+    #[test]
+    fn complete_runs() {
+        let run = Model {
+            id: 1,
+            started_at: chrono::Utc::now(),
+            finished_at: Some(chrono::Utc::now()),
+            pages_attempted: 3,
+            pages_succeeded: 3,
+            pages_failed: 0,
+            records_written: 60,
+            cards_seen: 60,
+            cards_rejected: 0,
+            empty_pages: 0,
+            drift: None,
+        };
+        assert!(run.is_complete());
+        assert!(
+            !Model {
+                finished_at: None,
+                ..run.clone()
+            }
+            .is_complete()
+        );
+        assert!(
+            !Model {
+                pages_succeeded: 2,
+                pages_failed: 1,
+                ..run.clone()
+            }
+            .is_complete()
+        );
+        assert!(
+            !Model {
+                drift: Some(
+                    "1 of 3 pages without cards".into()
+                ),
+                ..run.clone()
+            }
+            .is_complete()
+        );
+        // every page unchanged since the last run
+        assert!(
+            Model {
+                records_written: 0,
+                cards_seen: 0,
+                ..run.clone()
+            }
+            .is_complete()
+        );
+        assert!(
+            !Model {
+                pages_attempted: 0,
+                pages_succeeded: 0,
+                ..run
+            }
+            .is_complete()
+        );
+    }
+
+    #[test]
+    fn atomic_veto() {
+        let tally = Tally {
+            pages_attempted: 10,
+            pages_succeeded: 8,
+            pages_failed: 2,
+            records_written: 0,
+        };
+        assert_eq!(tally.veto(None, 2), None);
+        assert_eq!(
+            tally.veto(None, 1).as_deref(),
+            Some("2 of 10 pages failed")
+        );
+        assert_eq!(
+            tally
+                .veto(
+                    Some("1 of 10 pages without cards"),
+                    2
+                )
+                .as_deref(),
+            Some(
+                "parser drift, 1 of 10 pages without cards"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_start_finish() -> anyhow::Result<()> {
+        let test = TestDb::new().await?;
+        let db = &test.db;
+
+        let run = start(db).await?;
+        assert!(run.finished_at.is_none());
+        let tally = Tally {
+            pages_attempted: 2,
+            pages_succeeded: 1,
+            pages_failed: 1,
+            records_written: 20,
+        };
+        let run = finish(
+            db,
+            run.id,
+            tally,
+            &RunHealth::default(),
+            None,
+        )
+        .await?;
+        assert!(run.finished_at.is_some());
+        assert_eq!(run.records_written, 20);
+        Ok(())
+    }
+}
@@ -0,0 +1,47 @@
+//! the crawl_x database, its backend is chosen by the url
+//!
+//! `postgres://` urls are migrated with the `migration` crate in the
+//! schema of the profile, `sqlite:` ones need the `sqlite` feature, see
+//! `sqlite`.
+use sea_orm::DatabaseConnection;
+use utils::config::env::ENV_SETTINGS;
+
+// tests run on SQLite unless given a Postgres server, see `test_db`
+#[cfg(any(test, feature = "sqlite"))]
+mod sqlite;
+#[cfg(test)]
+pub mod test_db;
+
+/// connect to `url` with the crawl_x tables up to date
+pub async fn connect(
+    url: &str,
+) -> anyhow::Result<DatabaseConnection> {
+    if url.starts_with("sqlite:") {
+        return connect_sqlite(url).await;
+    }
+    log::info!(
+        "crawl_x on profile {}, schema {}",
+        ENV_SETTINGS.profile(),
+        migration::schema()
+    );
+    let db = migration::connect(url).await?;
+    migration::up(&db).await?;
+    Ok(db)
+}
+
+#[cfg(any(test, feature = "sqlite"))]
+async fn connect_sqlite(
+    url: &str,
+) -> anyhow::Result<DatabaseConnection> {
+    sqlite::connect(url).await
+}
+
+#[cfg(not(any(test, feature = "sqlite")))]
+async fn connect_sqlite(
+    url: &str,
+) -> anyhow::Result<DatabaseConnection> {
+    anyhow::bail!(
+        "{} needs the sqlite feature of excavate",
+        url
+    )
+}
@@ -0,0 +1,191 @@
+//! crawl_x on SQLite, for laptops and tests
+//!
+//! The migrations run here as on Postgres. A file from before they did
+//! has tables but no `seaql_migrations`, it can not be brought up to
+//! date and is refused: `export` it and `import` it into a new file.
+//! SQLite has no schemas, `DB_SCHEMA` is not used.
+use sea_orm::sea_query::{Alias, Expr, Query};
+use sea_orm::sqlx::sqlite::{
+    SqliteConnectOptions, SqliteJournalMode,
+    SqlitePoolOptions,
+};
+use sea_orm::{
+    ConnectionTrait, DatabaseConnection, DbErr,
+    SqlxSqliteConnector,
+};
+use std::str::FromStr;
+
+/// file of a `sqlite:` url, `:memory:` for an in-memory database
+fn database_file(url: &str) -> &str {
+    let path = url
+        .strip_prefix("sqlite://")
+        .or_else(|| url.strip_prefix("sqlite:"))
+        .unwrap_or(url);
+    match path.split('?').next().unwrap_or(path) {
+        "" | ":memory:" => ":memory:",
+        file => file,
+    }
+}
+
+pub async fn connect(
+    url: &str,
+) -> anyhow::Result<DatabaseConnection> {
+    let mut options = SqliteConnectOptions::from_str(url)?
+        .create_if_missing(true);
+    let mut pool = SqlitePoolOptions::new();
+    if database_file(url) == ":memory:" {
+        // every connection would open a database of its own
+        pool = pool
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None);
+    } else {
+        options =
+            options.journal_mode(SqliteJournalMode::Wal);
+    }
+    let pool = pool.connect_with(options).await?;
+    let db =
+        SqlxSqliteConnector::from_sqlx_sqlite_pool(pool);
+    if unmigrated(&db).await? {
+        anyhow::bail!(
+            "{} predates the SQLite migrations, export it and \
+             import it into a new file",
+            url
+        );
+    }
+    migration::up(&db).await?;
+    Ok(db)
+}
+
+/// true for a file with crawl_x tables the migrator never saw
+async fn unmigrated(
+    db: &DatabaseConnection,
+) -> Result<bool, DbErr> {
+    let name = Alias::new("name");
+    let tables = db
+        .query_all(
+            &Query::select()
+                .column(name.clone())
+                .from(Alias::new("sqlite_master"))
+                .and_where(Expr::col(name).is_in([
+                    "astroturfers_x",
+                    "seaql_migrations",
+                ]))
+                .to_owned(),
+        )
+        .await?;
+    let mut names = Vec::new();
+    for table in &tables {
+        names.push(table.try_get::<String>("", "name")?);
+    }
+    Ok(names == ["astroturfers_x"])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::{crawl_run, history, to_db};
+    use super::*;
+    use sea_orm::EntityTrait;
+
+    fn account(user_id: &str, name: &str) -> to_db::Model {
+        to_db::Model {
+            name: name.to_string(),
+            ..to_db::Model::test_account(user_id)
+        }
+    }
+
+    #[test]
+    fn database_files() {
+        assert_eq!(
+            database_file("sqlite::memory:"),
+            ":memory:"
+        );
+        assert_eq!(
+            database_file("sqlite://data/x.db?mode=rwc"),
+            "data/x.db"
+        );
+        assert_eq!(database_file("sqlite:x.db"), "x.db");
+    }
+
+    #[tokio::test]
+    async fn upsert_on_sqlite() -> anyhow::Result<()> {
+        let db = connect("sqlite::memory:").await?;
+        // migrated again, nothing is pending
+        migration::up(&db).await?;
+
+        let run = crawl_run::start(&db).await?;
+        let first = vec![
+            account("1830540823630675969", "a"),
+            account("1830540823630675970", "b"),
+        ];
+        let seen_at = chrono::Utc::now();
+        history::record(&db, &first, seen_at).await?;
+        to_db::upsert(
+            &db,
+            to_db::stamp(run.id, seen_at, first),
+        )
+        .await?;
+
+        let again = vec![
+            account("1830540823630675969", "a2"),
+            account("1830540823630675969", "a3"),
+        ];
+        let seen_at = chrono::Utc::now();
+        history::record(&db, &again, seen_at).await?;
+        let count = to_db::upsert(
+            &db,
+            to_db::stamp(run.id + 1, seen_at, again),
+        )
+        .await?;
+        assert_eq!(count, 1);
+
+        let row = to_db::Entity::find_by_id(
+            "1830540823630675969",
+        )
+        .one(&db)
+        .await?
+        .expect("upserted");
+        assert_eq!(row.name, "a3");
+        assert_eq!(row.first_seen_run, Some(run.id));
+        assert_eq!(row.last_seen_run, Some(run.id + 1));
+        assert!(row.id_created_at.is_some());
+
+        let names: Vec<String> =
+            history::timeline(&db, "1830540823630675969")
+                .await?
+                .into_iter()
+                .map(|identity| identity.name)
+                .collect();
+        assert_eq!(names, ["a", "a2", "a3"]);
+
+        let seen = to_db::touch_page(
+            &db,
+            run.id + 2,
+            "https://example.com/",
+        )
+        .await?;
+        assert_eq!(seen, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn refuse_an_unmigrated_file()
+    -> anyhow::Result<()> {
+        let file = std::env::temp_dir().join(format!(
+            "crawl_x_{}_unmigrated.db",
+            std::process::id()
+        ));
+        let url = format!("sqlite:{}", file.display());
+        let old = connect(&url).await?;
+        old.execute_unprepared(
+            "DROP TABLE seaql_migrations",
+        )
+        .await?;
+        old.close().await?;
+
+        let refused = connect(&url).await;
+        std::fs::remove_file(&file)?;
+        assert!(refused.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,94 @@
+//! a database of its own for every test
+//!
+//! With `TEST_DATABASE_URL` naming a Postgres server, a local one or a
+//! throwaway container, every `TestDb` migrates a fresh schema there and
+//! drops it again. Without one it is a SQLite in-memory database
+//! migrated the same way, so `cargo test` needs no server.
+use super::sqlite;
+use sea_orm::{
+    ConnectionTrait, Database, DatabaseConnection,
+};
+use std::sync::atomic::{AtomicU32, Ordering};
+use utils::config::env::ENV_SETTINGS;
+
+/// tests of one process run in parallel, each needs a schema
+static SCHEMAS: AtomicU32 = AtomicU32::new(0);
+
+pub struct TestDb {
+    pub db: DatabaseConnection,
+    /// url and schema of a Postgres test database, to drop
+    postgres: Option<(String, String)>,
+}
+
+impl TestDb {
+    pub async fn new() -> anyhow::Result<Self> {
+        let Some(url) =
+            ENV_SETTINGS.test_database_url.clone()
+        else {
+            return Ok(Self {
+                db: sqlite::connect("sqlite::memory:")
+                    .await?,
+                postgres: None,
+            });
+        };
+        let schema = format!(
+            "test_{}_{}",
+            std::process::id(),
+            SCHEMAS.fetch_add(1, Ordering::Relaxed)
+        );
+        let db =
+            migration::connect_in(&url, &schema).await?;
+        migration::up(&db).await?;
+        Ok(Self {
+            db,
+            postgres: Some((url, schema)),
+        })
+    }
+}
+
+impl Drop for TestDb {
+    fn drop(&mut self) {
+        let Some((url, schema)) = self.postgres.take()
+        else {
+            return;
+        };
+        // drop can not await, the schema goes on a runtime of its own
+        let dropped = std::thread::spawn(move || {
+            tokio::runtime::Runtime::new()?.block_on(
+                async {
+                    let db =
+                        Database::connect(&url).await?;
+                    db.execute_unprepared(&format!(
+                    "DROP SCHEMA IF EXISTS \"{}\" CASCADE",
+                    schema
+                ))
+                .await?;
+                    db.close().await?;
+                    anyhow::Ok(())
+                },
+            )
+        })
+        .join();
+        if let Ok(Err(e)) = dropped {
+            log::warn!("test schema left behind: {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::crawl_run;
+    use super::*;
+    use sea_orm::{EntityTrait, PaginatorTrait};
+
+    #[tokio::test]
+    async fn isolated() -> anyhow::Result<()> {
+        let a = TestDb::new().await?;
+        let b = TestDb::new().await?;
+        crawl_run::start(&a.db).await?;
+        let runs = crawl_run::Entity::find();
+        assert_eq!(runs.clone().count(&a.db).await?, 1);
+        assert_eq!(runs.count(&b.db).await?, 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,569 @@
+//! snapshots of astroturfers_x as CSV, JSON Lines or Parquet
+//!
+//! Rows are streamed in `user_id` order, so the same rows give the same
+//! file. Every export writes `<file>.manifest.json` next to it with the
+//! row count, the schema version and the SHA-256 of the file. Parquet
+//! needs the `parquet` feature.
+use super::to_db::{
+    COLUMNS, Column, Entity, Model, csv_line, fields,
+};
+use futures::TryStreamExt;
+use sea_orm::entity::prelude::*;
+use sea_orm::{Condition, QueryOrder};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[cfg(feature = "parquet")]
+mod columnar;
+
+/// file formats, chosen by the extension of the file
+#[derive(
+    Clone, Copy, Debug, PartialEq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Csv,
+    Jsonl,
+    Parquet,
+}
+
+impl Format {
+    /// format of `path` by its extension
+    pub fn of(path: &Path) -> anyhow::Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+        extension.parse().map_err(|_| {
+            anyhow::anyhow!(
+                "{} is not a .csv, .jsonl or .parquet file",
+                path.display()
+            )
+        })
+    }
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "jsonl" => Ok(Self::Jsonl),
+            "parquet" => Ok(Self::Parquet),
+            _ => anyhow::bail!("unknown format {}", s),
+        }
+    }
+}
+
+/// rows to export, all live accounts by default
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Filter {
+    /// earliest `register_time`, inclusive
+    pub registered_from: Option<Date>,
+    /// latest `register_time`, inclusive
+    pub registered_to: Option<Date>,
+    pub min_changed_name_count: Option<u32>,
+    pub region: Option<String>,
+    /// also the accounts gone from the site, see `removed_at`
+    pub include_removed: bool,
+}
+
+impl Filter {
+    /// the filter as a `WHERE`, `report` counts the same rows
+    pub(super) fn condition(&self) -> Condition {
+        let mut condition = Condition::all();
+        if let Some(from) = self.registered_from {
+            condition = condition
+                .add(Column::RegisterTime.gte(from));
+        }
+        if let Some(to) = self.registered_to {
+            condition =
+                condition.add(Column::RegisterTime.lte(to));
+        }
+        if let Some(count) = self.min_changed_name_count {
+            condition = condition
+                .add(Column::ChangedNameCount.gte(count));
+        }
+        if let Some(region) = &self.region {
+            condition = condition
+                .add(Column::Region.eq(region.as_str()));
+        }
+        if !self.include_removed {
+            condition =
+                condition.add(Column::RemovedAt.is_null());
+        }
+        condition
+    }
+}
+
+/// what an export wrote, saved as `<file>.manifest.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    /// file name of the export, next to the manifest
+    pub file: String,
+    pub format: Format,
+    pub rows: u64,
+    pub bytes: u64,
+    /// lowercase hex SHA-256 of the file
+    pub sha256: String,
+    /// latest migration, see `migration::version`
+    pub schema_version: String,
+    pub filter: Filter,
+    pub exported_at: DateTimeUtc,
+}
+
+/// manifest path of export `path`
+pub fn manifest_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".manifest.json");
+    PathBuf::from(name)
+}
+
+/// Write the rows of `filter` to `path`, in the format of its
+/// extension, then its manifest.
+pub async fn export(
+    db: &DatabaseConnection,
+    filter: &Filter,
+    path: &Path,
+) -> anyhow::Result<Manifest> {
+    let format = Format::of(path)?;
+    let mut sink = Sink::create(format, path)?;
+    let mut rows = std::pin::pin!(
+        Entity::find()
+            .filter(filter.condition())
+            .order_by_asc(Column::UserId)
+            .stream(db)
+            .await?
+    );
+    let mut count = 0;
+    while let Some(model) = rows.try_next().await? {
+        sink.write(&model)?;
+        count += 1;
+    }
+    let out = sink.finish()?;
+    let (bytes, sha256) = out.finish()?;
+    let manifest = Manifest {
+        file: path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        format,
+        rows: count,
+        bytes,
+        sha256,
+        schema_version: migration::version(),
+        filter: filter.clone(),
+        exported_at: chrono::Utc::now(),
+    };
+    std::fs::write(
+        manifest_path(path),
+        serde_json::to_string_pretty(&manifest)?,
+    )?;
+    log::info!(
+        "exported {} rows to {}",
+        manifest.rows,
+        path.display()
+    );
+    Ok(manifest)
+}
+
+/// `model` as a JSON object, NULL columns as `null`
+fn json_row(model: &Model) -> serde_json::Value {
+    let Model {
+        user_id,
+        name,
+        handle,
+        profile_url,
+        avatar,
+        register_time,
+        region,
+        source,
+        changed_name_count,
+        first_seen_run,
+        last_seen_run,
+        page_url,
+        removed_at,
+        id_created_at,
+        last_seen_at,
+    } = model;
+    serde_json::json!({
+        "user_id": user_id,
+        "name": name,
+        "handle": handle,
+        "profile_url": profile_url,
+        "avatar": avatar,
+        "register_time": register_time,
+        "region": region,
+        "source": source,
+        "changed_name_count": changed_name_count,
+        "first_seen_run": first_seen_run,
+        "last_seen_run": last_seen_run,
+        "page_url": page_url,
+        "removed_at": removed_at,
+        "id_created_at": id_created_at,
+        "last_seen_at": last_seen_at,
+    })
+}
+
+/// counts and hashes the bytes written through it
+struct Digesting<W> {
+    inner: W,
+    sha256: Sha256,
+    bytes: u64,
+}
+
+impl<W: Write> Digesting<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            sha256: Sha256::new(),
+            bytes: 0,
+        }
+    }
+
+    /// flush, the byte count and the hex digest
+    fn finish(mut self) -> io::Result<(u64, String)> {
+        self.inner.flush()?;
+        Ok((
+            self.bytes,
+            format!("{:x}", self.sha256.finalize()),
+        ))
+    }
+}
+
+impl<W: Write> Write for Digesting<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.sha256.update(&buf[..n]);
+        self.bytes += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+type Out = Digesting<BufWriter<File>>;
+
+/// rows on their way into a file of one format
+enum Sink {
+    Csv(Out),
+    Jsonl(Out),
+    #[cfg(feature = "parquet")]
+    Parquet(columnar::ParquetWriter<Out>),
+}
+
+impl Sink {
+    /// `path` truncated, checked for the feature of `format` first
+    fn create(
+        format: Format,
+        path: &Path,
+    ) -> anyhow::Result<Self> {
+        #[cfg(not(feature = "parquet"))]
+        if format == Format::Parquet {
+            anyhow::bail!(
+                "parquet exports need the parquet feature of excavate"
+            );
+        }
+        let mut out = Digesting::new(BufWriter::new(
+            File::create(path)?,
+        ));
+        Ok(match format {
+            Format::Csv => {
+                let mut header = String::new();
+                csv_line(
+                    &mut header,
+                    COLUMNS.iter().map(|column| {
+                        Some(column.as_str().to_string())
+                    }),
+                );
+                out.write_all(header.as_bytes())?;
+                Self::Csv(out)
+            }
+            Format::Jsonl => Self::Jsonl(out),
+            #[cfg(feature = "parquet")]
+            Format::Parquet => Self::Parquet(
+                columnar::ParquetWriter::new(out)?,
+            ),
+            #[cfg(not(feature = "parquet"))]
+            Format::Parquet => unreachable!(),
+        })
+    }
+
+    fn write(
+        &mut self,
+        model: &Model,
+    ) -> anyhow::Result<()> {
+        match self {
+            Self::Csv(out) => {
+                let mut line = String::new();
+                csv_line(&mut line, fields(model));
+                out.write_all(line.as_bytes())?;
+            }
+            Self::Jsonl(out) => {
+                serde_json::to_writer(
+                    &mut *out,
+                    &json_row(model),
+                )?;
+                out.write_all(b"\n")?;
+            }
+            #[cfg(feature = "parquet")]
+            Self::Parquet(writer) => writer.write(model)?,
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> anyhow::Result<Out> {
+        match self {
+            Self::Csv(out) | Self::Jsonl(out) => Ok(out),
+            #[cfg(feature = "parquet")]
+            Self::Parquet(writer) => writer.finish(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::crawl_run;
+    use super::super::db::test_db::TestDb;
+    use super::super::to_db;
+    use super::*;
+    use sea_orm::Iterable;
+
+    fn account(
+        user_id: &str,
+        registered: (i32, u32, u32),
+        changed_name_count: u32,
+        region: &str,
+    ) -> Model {
+        let (y, m, d) = registered;
+        Model {
+            name: format!("name, \"{}\"", user_id),
+            handle: format!("h{}", user_id),
+            profile_url: format!(
+                "https://x.com/h{}",
+                user_id
+            ),
+            register_time: Date::from_ymd_opt(y, m, d),
+            region: region.to_string(),
+            changed_name_count,
+            ..Model::test_account(user_id)
+        }
+    }
+
+    /// three live accounts and a removed one
+    async fn seeded() -> anyhow::Result<TestDb> {
+        let test = TestDb::new().await?;
+        let run = crawl_run::start(&test.db).await?;
+        to_db::upsert(
+            &test.db,
+            to_db::stamp(
+                run.id,
+                run.started_at,
+                vec![
+                    account(
+                        "1830540823630675969",
+                        (2024, 9, 2),
+                        3,
+                        "中国",
+                    ),
+                    account(
+                        "1672931804427603968",
+                        (2023, 6, 25),
+                        0,
+                        "中国",
+                    ),
+                    account(
+                        "1544278901236539392",
+                        (2022, 7, 5),
+                        5,
+                        "美国",
+                    ),
+                    account(
+                        "1444278901236539392",
+                        (2021, 10, 2),
+                        9,
+                        "中国",
+                    ),
+                ],
+            ),
+        )
+        .await?;
+        Entity::update_many()
+            .col_expr(
+                Column::RemovedAt,
+                Expr::value(Some(chrono::Utc::now())),
+            )
+            .filter(
+                Column::UserId.eq("1444278901236539392"),
+            )
+            .exec(&test.db)
+            .await?;
+        Ok(test)
+    }
+
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "export_{}_{}",
+            std::process::id(),
+            name
+        ))
+    }
+
+    #[test]
+    fn json_covers_every_column() {
+        let row =
+            json_row(&account("1", (2024, 9, 2), 0, ""));
+        let mut keys: Vec<&str> = row
+            .as_object()
+            .expect("an object")
+            .keys()
+            .map(String::as_str)
+            .collect();
+        let mut columns: Vec<&str> =
+            Column::iter().map(|c| c.as_str()).collect();
+        keys.sort();
+        columns.sort();
+        assert_eq!(keys, columns);
+    }
+
+    #[tokio::test]
+    async fn csv_with_manifest() -> anyhow::Result<()> {
+        let test = seeded().await?;
+        let path = temp_file("changed.csv");
+        let filter = Filter {
+            min_changed_name_count: Some(1),
+            ..Filter::default()
+        };
+        let manifest =
+            export(&test.db, &filter, &path).await?;
+
+        let csv = std::fs::read_to_string(&path)?;
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(manifest.rows, 2);
+        assert_eq!(lines.len(), 3);
+        assert!(
+            lines[0].starts_with("\"user_id\",\"name\",")
+        );
+        // in user_id order
+        assert!(lines[1].starts_with(
+            "\"1544278901236539392\",\"name, \"\"1544278901236539392\"\"\""
+        ));
+        assert!(
+            lines[2]
+                .starts_with("\"1830540823630675969\",")
+        );
+        assert_eq!(
+            manifest.sha256,
+            format!("{:x}", Sha256::digest(csv.as_bytes()))
+        );
+        assert_eq!(manifest.bytes, csv.len() as u64);
+        assert_eq!(
+            manifest.schema_version,
+            migration::version()
+        );
+
+        let saved: serde_json::Value =
+            serde_json::from_str(
+                &std::fs::read_to_string(manifest_path(
+                    &path,
+                ))?,
+            )?;
+        assert_eq!(saved["rows"], 2);
+        assert_eq!(saved["format"], "csv");
+        assert_eq!(
+            saved["filter"]["min_changed_name_count"],
+            1
+        );
+
+        // the same rows, the same file
+        let again =
+            export(&test.db, &filter, &path).await?;
+        assert_eq!(again.sha256, manifest.sha256);
+        std::fs::remove_file(manifest_path(&path))?;
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn jsonl_filters() -> anyhow::Result<()> {
+        let test = seeded().await?;
+        let path = temp_file("china.jsonl");
+        let filter = Filter {
+            registered_from: Date::from_ymd_opt(2021, 1, 1),
+            registered_to: Date::from_ymd_opt(2023, 12, 31),
+            region: Some("中国".to_string()),
+            include_removed: true,
+            ..Filter::default()
+        };
+        let manifest =
+            export(&test.db, &filter, &path).await?;
+
+        let rows: Vec<serde_json::Value> =
+            std::fs::read_to_string(&path)?
+                .lines()
+                .map(serde_json::from_str)
+                .collect::<Result<_, _>>()?;
+        let ids: Vec<&str> = rows
+            .iter()
+            .map(|row| {
+                row["user_id"].as_str().expect("an id")
+            })
+            .collect();
+        assert_eq!(
+            ids,
+            ["1444278901236539392", "1672931804427603968"]
+        );
+        assert_eq!(manifest.rows, 2);
+        assert_eq!(rows[1]["register_time"], "2023-06-25");
+        assert_eq!(rows[1]["changed_name_count"], 0);
+        assert!(rows[0]["removed_at"].is_string());
+        assert!(rows[1]["removed_at"].is_null());
+        std::fs::remove_file(manifest_path(&path))?;
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(
+            Format::of(Path::new("a/b.parquet")).ok(),
+            Some(Format::Parquet)
+        );
+        assert!(Format::of(Path::new("b.json")).is_err());
+        assert!(Format::of(Path::new("csv")).is_err());
+    }
+
+    #[cfg(feature = "parquet")]
+    #[tokio::test]
+    async fn parquet_rows() -> anyhow::Result<()> {
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let test = seeded().await?;
+        let path = temp_file("all.parquet");
+        let manifest =
+            export(&test.db, &Filter::default(), &path)
+                .await?;
+        assert_eq!(manifest.rows, 3);
+
+        let reader =
+            ParquetRecordBatchReaderBuilder::try_new(
+                File::open(&path)?,
+            )?
+            .build()?;
+        let mut rows = 0;
+        for batch in reader {
+            rows += batch?.num_rows();
+        }
+        assert_eq!(rows, 3);
+        std::fs::remove_file(manifest_path(&path))?;
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+//! astroturfers_x rows as Parquet, columns typed like the table
+use super::super::to_db::{COLUMNS, Model};
+use arrow_array::types::Date32Type;
+use arrow_array::{
+    ArrayRef, Date32Array, Int64Array, RecordBatch,
+    StringArray, TimestampMicrosecondArray, UInt32Array,
+};
+use arrow_schema::{
+    DataType, Field, Schema, SchemaRef, TimeUnit,
+};
+use parquet::arrow::ArrowWriter;
+use sea_orm::entity::prelude::{DateTimeUtc, IdenStatic};
+use std::io::Write;
+use std::sync::Arc;
+
+/// rows buffered before they are turned into columns
+const BATCH_ROWS: usize = 8_192;
+
+pub struct ParquetWriter<W: Write + Send> {
+    writer: ArrowWriter<W>,
+    rows: Vec<Model>,
+}
+
+impl<W: Write + Send> ParquetWriter<W> {
+    pub fn new(out: W) -> anyhow::Result<Self> {
+        Ok(Self {
+            writer: ArrowWriter::try_new(
+                out,
+                schema(),
+                None,
+            )?,
+            rows: Vec::with_capacity(BATCH_ROWS),
+        })
+    }
+
+    pub fn write(
+        &mut self,
+        model: &Model,
+    ) -> anyhow::Result<()> {
+        self.rows.push(model.clone());
+        if self.rows.len() >= BATCH_ROWS {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// the buffered rows, then the footer, returns `out`
+    pub fn finish(mut self) -> anyhow::Result<W> {
+        self.flush()?;
+        Ok(self.writer.into_inner()?)
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        if !self.rows.is_empty() {
+            self.writer.write(&batch(&self.rows)?)?;
+            self.rows.clear();
+        }
+        Ok(())
+    }
+}
+
+/// the columns of `COLUMNS`, NULL where the table allows it
+fn schema() -> SchemaRef {
+    let timestamp = DataType::Timestamp(
+        TimeUnit::Microsecond,
+        Some("UTC".into()),
+    );
+    let types = [
+        (DataType::Utf8, false),
+        (DataType::Utf8, false),
+        (DataType::Utf8, false),
+        (DataType::Utf8, false),
+        (DataType::Utf8, false),
+        (DataType::Date32, true),
+        (DataType::Utf8, false),
+        (DataType::Utf8, false),
+        (DataType::UInt32, false),
+        (DataType::Int64, true),
+        (DataType::Int64, true),
+        (DataType::Utf8, false),
+        (timestamp.clone(), true),
+        (timestamp.clone(), true),
+        (timestamp, true),
+    ];
+    let fields: Vec<Field> = COLUMNS
+        .iter()
+        .zip(types)
+        .map(|(column, (data_type, nullable))| {
+            Field::new(column.as_str(), data_type, nullable)
+        })
+        .collect();
+    Arc::new(Schema::new(fields))
+}
+
+/// how `batch` takes a column out of a row
+type Get<T> = fn(&Model) -> T;
+
+fn batch(rows: &[Model]) -> anyhow::Result<RecordBatch> {
+    let text = |field: fn(&Model) -> &String| -> ArrayRef {
+        Arc::new(StringArray::from_iter_values(
+            rows.iter().map(field),
+        ))
+    };
+    let run = |field: Get<Option<i64>>| -> ArrayRef {
+        Arc::new(Int64Array::from_iter(
+            rows.iter().map(field),
+        ))
+    };
+    let time =
+        |field: Get<Option<DateTimeUtc>>| -> ArrayRef {
+            let micros = rows.iter().map(|m| {
+                field(m).map(|at| at.timestamp_micros())
+            });
+            Arc::new(
+                TimestampMicrosecondArray::from_iter(
+                    micros,
+                )
+                .with_timezone("UTC"),
+            )
+        };
+    let register_time = rows.iter().map(|m| {
+        m.register_time.map(Date32Type::from_naive_date)
+    });
+    let columns: Vec<ArrayRef> = vec![
+        text(|m| &m.user_id),
+        text(|m| &m.name),
+        text(|m| &m.handle),
+        text(|m| &m.profile_url),
+        text(|m| &m.avatar),
+        Arc::new(Date32Array::from_iter(register_time)),
+        text(|m| &m.region),
+        text(|m| &m.source),
+        Arc::new(UInt32Array::from_iter_values(
+            rows.iter().map(|m| m.changed_name_count),
+        )),
+        run(|m| m.first_seen_run),
+        run(|m| m.last_seen_run),
+        text(|m| &m.page_url),
+        time(|m| m.removed_at),
+        time(|m| m.id_created_at),
+        time(|m| m.last_seen_at),
+    ];
+    Ok(RecordBatch::try_new(schema(), columns)?)
+}
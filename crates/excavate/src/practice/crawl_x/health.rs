@@ -0,0 +1,276 @@
+//! extraction health of crawled pages, a markup change of the site
+//! shows up here instead of as empty rows
+use crate::extract::Page;
+use sea_orm::ActiveValue::{NotSet, Set};
+use sea_orm::entity::prelude::*;
+use std::collections::BTreeSet;
+use utils::config::env::ENV_SETTINGS;
+
+/// separator of the "label：value" meta items of a card
+const META_SEPARATOR: &str = "：";
+
+/// a suspect page of a run, its html is snapshot `snapshot_id`
+#[derive(
+    Clone, Debug, PartialEq, Eq, DeriveEntityModel,
+)]
+#[sea_orm(table_name = "page_health")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub run_id: i64,
+    pub url: String,
+    pub snapshot_id: i64,
+    pub cards: u32,
+    pub rejected: u32,
+    /// meta labels no field reads, one per line
+    pub unknown_labels: String,
+    /// why cards were rejected, one per line
+    pub errors: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// how extraction went on one page
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PageHealth {
+    pub cards: u32,
+    /// cards with a missing or invalid field
+    pub rejected: u32,
+    pub unknown_labels: BTreeSet<String>,
+    pub errors: Vec<String>,
+}
+
+impl PageHealth {
+    pub fn of<T>(page: &Page<T>) -> Self {
+        Self {
+            cards: page.containers() as u32,
+            rejected: page.errors.len() as u32,
+            unknown_labels: page
+                .unknown_meta
+                .iter()
+                .map(|item| {
+                    item.split_once(META_SEPARATOR)
+                        .map_or(
+                            item.as_str(),
+                            |(label, _)| label.trim(),
+                        )
+                        .to_string()
+                })
+                .collect(),
+            errors: page
+                .errors
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+        }
+    }
+
+    /// no card at all, a rejected card or a label we do not read
+    pub fn is_suspect(&self) -> bool {
+        self.cards == 0
+            || self.rejected > 0
+            || !self.unknown_labels.is_empty()
+    }
+}
+
+/// limits of a run, above any of them the run fails
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Thresholds {
+    pub max_empty_pages: u32,
+    pub max_rejected_share: f64,
+    pub max_unknown_labels: u32,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            max_empty_pages: 0,
+            max_rejected_share: 0.05,
+            max_unknown_labels: 0,
+        }
+    }
+}
+
+impl Thresholds {
+    /// defaults overridden by the `DRIFT_*` variables
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            max_empty_pages: ENV_SETTINGS
+                .drift_max_empty_pages
+                .unwrap_or(default.max_empty_pages),
+            max_rejected_share: ENV_SETTINGS
+                .drift_max_rejected_share
+                .unwrap_or(default.max_rejected_share),
+            max_unknown_labels: ENV_SETTINGS
+                .drift_max_unknown_labels
+                .unwrap_or(default.max_unknown_labels),
+        }
+    }
+}
+
+/// health of the pages a run parsed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RunHealth {
+    pub pages: u32,
+    pub empty_pages: u32,
+    pub cards: u32,
+    pub rejected: u32,
+    pub unknown_labels: BTreeSet<String>,
+}
+
+impl RunHealth {
+    pub fn add(&mut self, page: &PageHealth) {
+        self.pages += 1;
+        if page.cards == 0 {
+            self.empty_pages += 1;
+        }
+        self.cards += page.cards;
+        self.rejected += page.rejected;
+        self.unknown_labels
+            .extend(page.unknown_labels.iter().cloned());
+    }
+
+    /// why the run breaks `thresholds`, `None` while it does not
+    pub fn drift(
+        &self,
+        thresholds: &Thresholds,
+    ) -> Option<String> {
+        let mut reasons = Vec::new();
+        if self.empty_pages > thresholds.max_empty_pages {
+            reasons.push(format!(
+                "{} of {} pages without cards",
+                self.empty_pages, self.pages
+            ));
+        }
+        if self.cards > 0
+            && self.rejected as f64 / self.cards as f64
+                > thresholds.max_rejected_share
+        {
+            reasons.push(format!(
+                "{} of {} cards rejected",
+                self.rejected, self.cards
+            ));
+        }
+        if self.unknown_labels.len() as u32
+            > thresholds.max_unknown_labels
+        {
+            let labels: Vec<&str> = self
+                .unknown_labels
+                .iter()
+                .map(String::as_str)
+                .collect();
+            reasons.push(format!(
+                "unknown meta labels {}",
+                labels.join(", ")
+            ));
+        }
+        (!reasons.is_empty()).then(|| reasons.join("; "))
+    }
+}
+
+/// keep a suspect page of run `run_id` for inspection
+pub async fn record<C: ConnectionTrait>(
+    db: &C,
+    run_id: i64,
+    url: &str,
+    snapshot_id: i64,
+    health: &PageHealth,
+) -> Result<Model, DbErr> {
+    let labels: Vec<&str> = health
+        .unknown_labels
+        .iter()
+        .map(String::as_str)
+        .collect();
+    ActiveModel {
+        id: NotSet,
+        run_id: Set(run_id),
+        url: Set(url.to_string()),
+        snapshot_id: Set(snapshot_id),
+        cards: Set(health.cards),
+        rejected: Set(health.rejected),
+        unknown_labels: Set(labels.join("\n")),
+        errors: Set(health.errors.join("\n")),
+    }
+    .insert(db)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::ExtractError;
+
+    fn page(
+        records: usize,
+        errors: usize,
+        unknown_meta: &[&str],
+    ) -> PageHealth {
+        PageHealth::of(&Page {
+            records: vec![(); records],
+            errors: vec![
+                ExtractError::Missing {
+                    field: "user_id",
+                    source: "div.user-id",
+                };
+                errors
+            ],
+            unknown_meta: unknown_meta
+                .iter()
+                .map(|item| item.to_string())
+                .collect(),
+        })
+    }
+
+    #[test]
+    fn page_health() {
+        let health = page(19, 1, &["粉丝：12", "粉丝：40"]);
+        assert_eq!(
+            (health.cards, health.rejected),
+            (20, 1)
+        );
+        assert_eq!(
+            health.unknown_labels,
+            BTreeSet::from(["粉丝".to_string()])
+        );
+        assert!(health.is_suspect());
+        assert!(!page(20, 0, &[]).is_suspect());
+        assert!(page(0, 0, &[]).is_suspect());
+    }
+
+    #[test]
+    fn run_drift() {
+        let thresholds = Thresholds::default();
+        let mut run = RunHealth::default();
+        for _ in 0..10 {
+            run.add(&page(20, 0, &[]));
+        }
+        run.add(&page(19, 1, &[]));
+        assert_eq!(run.drift(&thresholds), None);
+
+        run.add(&page(0, 0, &[]));
+        run.add(&page(5, 15, &["粉丝：12"]));
+        let drift = run.drift(&thresholds).unwrap();
+        assert!(
+            drift.contains("1 of 13 pages"),
+            "{}",
+            drift
+        );
+        assert!(
+            drift.contains("16 of 240 cards"),
+            "{}",
+            drift
+        );
+        assert!(drift.contains("粉丝"), "{}", drift);
+
+        let lenient = Thresholds {
+            max_empty_pages: 1,
+            max_rejected_share: 0.1,
+            max_unknown_labels: 1,
+        };
+        assert_eq!(run.drift(&lenient), None);
+    }
+}
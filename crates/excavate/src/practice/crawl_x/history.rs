@@ -0,0 +1,135 @@
+//! every identity an account of astroturfers_x was seen with
+use super::to_db;
+use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{ActiveValue::Set, Iterable, QueryOrder};
+
+/// one row per `(user_id, name, handle)`
+#[derive(
+    Clone, Debug, PartialEq, Eq, DeriveEntityModel,
+)]
+#[sea_orm(table_name = "astroturfers_x_history")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub user_id: String,
+    pub name: String,
+    pub handle: String,
+    /// last avatar seen with this identity
+    pub avatar: String,
+    /// last `改名次数：` seen with this identity
+    pub changed_name_count: u32,
+    pub first_seen: DateTimeUtc,
+    pub last_seen: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// `ON CONFLICT` of every write to the history table
+pub fn on_conflict() -> OnConflict {
+    OnConflict::columns([
+        Column::UserId,
+        Column::Name,
+        Column::Handle,
+    ])
+    .update_columns([
+        Column::Avatar,
+        Column::ChangedNameCount,
+        Column::LastSeen,
+    ])
+    .to_owned()
+}
+
+/// Record the identities of `models` as seen at `seen_at`, a known
+/// identity only moves its `last_seen`.
+pub async fn record<'m, C: ConnectionTrait>(
+    db: &C,
+    models: impl IntoIterator<Item = &'m to_db::Model>,
+    seen_at: DateTimeUtc,
+) -> Result<(), DbErr> {
+    let identities = to_db::last_per_key(
+        models.into_iter().collect(),
+        |m| {
+            (
+                m.user_id.clone(),
+                m.name.clone(),
+                m.handle.clone(),
+            )
+        },
+    );
+    let per_statement = to_db::rows_per_statement(
+        db.get_database_backend(),
+        Column::iter().count(),
+    );
+    for chunk in identities.chunks(per_statement) {
+        let active_models =
+            chunk.iter().map(|m| ActiveModel {
+                user_id: Set(m.user_id.clone()),
+                name: Set(m.name.clone()),
+                handle: Set(m.handle.clone()),
+                avatar: Set(m.avatar.clone()),
+                changed_name_count: Set(
+                    m.changed_name_count
+                ),
+                first_seen: Set(seen_at),
+                last_seen: Set(seen_at),
+                ..Default::default()
+            });
+        Entity::insert_many(active_models)
+            .on_conflict(on_conflict())
+            .exec(db)
+            .await?;
+    }
+    Ok(())
+}
+
+/// identities of `user_id`, oldest first
+pub async fn timeline<C: ConnectionTrait>(
+    db: &C,
+    user_id: &str,
+) -> Result<Vec<Model>, DbErr> {
+    Entity::find()
+        .filter(Column::UserId.eq(user_id))
+        .order_by_asc(Column::FirstSeen)
+        .order_by_asc(Column::Id)
+        .all(db)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::db::test_db::TestDb;
+    use super::*;
+    use chrono::SubsecRound;
+
+    fn identity(name: &str, handle: &str) -> to_db::Model {
+        to_db::Model {
+            name: name.to_string(),
+            handle: handle.to_string(),
+            ..to_db::Model::test_account("history-test")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_timeline() -> anyhow::Result<()> {
+        let test = TestDb::new().await?;
+        let db = &test.db;
+
+        let first = chrono::Utc::now().trunc_subsecs(0);
+        let later = first + chrono::Duration::days(1);
+        record(db, &[identity("a", "@a")], first).await?;
+        record(db, &[identity("b", "@b")], later).await?;
+        record(db, &[identity("a", "@a")], later).await?;
+
+        let identities =
+            timeline(db, "history-test").await?;
+        assert_eq!(identities.len(), 2);
+        assert_eq!(identities[0].name, "a");
+        assert_eq!(identities[0].last_seen, later);
+        assert_eq!(identities[1].handle, "@b");
+        Ok(())
+    }
+}
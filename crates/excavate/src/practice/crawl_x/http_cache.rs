@@ -0,0 +1,112 @@
+//! validators of the last fetch of every page, for conditional
+//! requests on the next run
+use sea_orm::ActiveValue::Set;
+use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::OnConflict;
+use std::collections::HashMap;
+use utils::tools::fetch::Validators;
+
+#[derive(
+    Clone, Debug, PartialEq, Eq, DeriveEntityModel,
+)]
+#[sea_orm(table_name = "http_cache")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub updated_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// validators of every cached url
+pub async fn load_all<C: ConnectionTrait>(
+    db: &C,
+) -> Result<HashMap<String, Validators>, DbErr> {
+    let cached = Entity::find().all(db).await?;
+    Ok(cached
+        .into_iter()
+        .map(|m| {
+            let validators = Validators {
+                etag: m.etag,
+                last_modified: m.last_modified,
+            };
+            (m.url, validators)
+        })
+        .collect())
+}
+
+/// remember the validators of `url`, forget it when there are none
+pub async fn store<C: ConnectionTrait>(
+    db: &C,
+    url: &str,
+    validators: &Validators,
+) -> Result<(), DbErr> {
+    if validators.is_empty() {
+        Entity::delete_by_id(url).exec(db).await?;
+        return Ok(());
+    }
+    let cached = ActiveModel {
+        url: Set(url.to_string()),
+        etag: Set(validators.etag.clone()),
+        last_modified: Set(validators
+            .last_modified
+            .clone()),
+        updated_at: Set(chrono::Utc::now()),
+    };
+    Entity::insert(cached)
+        .on_conflict(
+            OnConflict::column(Column::Url)
+                .update_columns([
+                    Column::Etag,
+                    Column::LastModified,
+                    Column::UpdatedAt,
+                ])
+                .to_owned(),
+        )
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::db::test_db::TestDb;
+    use super::*;
+
+    #[tokio::test]
+    async fn store_and_load() -> anyhow::Result<()> {
+        let test = TestDb::new().await?;
+        let db = &test.db;
+        let (first, second) = (
+            "https://example.com/",
+            "https://example.com/2",
+        );
+        let tagged = |etag: &str| Validators {
+            etag: Some(etag.to_string()),
+            last_modified: Some(
+                "Sat, 17 Oct 2026 08:00:00 GMT".to_string(),
+            ),
+        };
+        store(db, first, &tagged("\"a\"")).await?;
+        store(db, second, &tagged("\"b\"")).await?;
+        // a page fetched again replaces its validators
+        store(db, second, &tagged("\"c\"")).await?;
+        let cached = load_all(db).await?;
+        assert_eq!(cached.len(), 2);
+        assert_eq!(cached[second], tagged("\"c\""));
+
+        // an answer without validators forgets the page
+        store(db, first, &Validators::default()).await?;
+        let cached = load_all(db).await?;
+        assert_eq!(
+            cached.keys().collect::<Vec<_>>(),
+            [second]
+        );
+        Ok(())
+    }
+}
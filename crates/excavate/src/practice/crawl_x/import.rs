@@ -0,0 +1,778 @@
+//! datasets of `export` back into astroturfers_x
+//!
+//! CSV and JSON Lines files are read row by row. Every row is parsed
+//! into a `to_db::Model`, normalized and validated like a crawled one,
+//! and upserted with `to_db::upsert_with` by the `Conflict` strategy.
+//! Rows that fail are reported by line and left out. The import is one
+//! transaction, a dry run rolls it back. A file with a manifest next to
+//! it must match its checksum. The run ids of the exporting database
+//! mean nothing here, imported rows get none and stored ones keep
+//! theirs. Every valid row goes into the history table like a crawled
+//! one, seen at its `last_seen_at`.
+use super::export::{self, Format, Manifest};
+use super::to_db::{self, Column, Model};
+use super::{history, normalize, validate};
+use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{
+    DatabaseTransaction, Iterable, TransactionTrait,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::str::FromStr;
+
+/// valid rows upserted together
+const BATCH_ROWS: usize = 5_000;
+
+/// what to do with a row whose `user_id` is already stored
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Serialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Conflict {
+    /// the imported row when it was seen later, by `last_seen_at` or
+    /// else the `exported_at` of its manifest
+    #[default]
+    KeepNewest,
+    KeepExisting,
+    Overwrite,
+}
+
+impl FromStr for Conflict {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "keep-newest" => Ok(Self::KeepNewest),
+            "keep-existing" => Ok(Self::KeepExisting),
+            "overwrite" => Ok(Self::Overwrite),
+            _ => anyhow::bail!(
+                "{} is not keep-newest, keep-existing or overwrite",
+                s
+            ),
+        }
+    }
+}
+
+impl Conflict {
+    fn on_conflict(self) -> OnConflict {
+        let mut on_conflict =
+            OnConflict::column(Column::UserId);
+        let columns = Column::iter().filter(|c| {
+            !matches!(
+                c,
+                Column::UserId
+                    | Column::FirstSeenRun
+                    | Column::LastSeenRun
+            )
+        });
+        match self {
+            Self::KeepNewest => on_conflict
+                .update_columns(columns)
+                .action_and_where(Expr::cust(
+                    "excluded.last_seen_at > \
+                     astroturfers_x.last_seen_at \
+                     OR astroturfers_x.last_seen_at IS NULL",
+                )),
+            Self::KeepExisting => on_conflict.do_nothing(),
+            Self::Overwrite => {
+                on_conflict.update_columns(columns)
+            }
+        };
+        on_conflict
+    }
+}
+
+/// a row that was left out
+#[derive(Debug, Clone, Serialize)]
+pub struct RowError {
+    /// line of the file, from 1
+    pub line: u64,
+    pub user_id: Option<String>,
+    pub problems: Vec<String>,
+}
+
+/// what an import did, or would do on a dry run
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Report {
+    pub conflict: Conflict,
+    pub dry_run: bool,
+    /// the file matched the checksum of its manifest
+    pub verified: bool,
+    /// rows read, valid or not
+    pub rows: u64,
+    /// rows inserted or updated
+    pub written: u64,
+    /// valid rows the strategy or a later duplicate left out
+    pub kept: u64,
+    pub errors: Vec<RowError>,
+}
+
+/// Import the .csv or .jsonl file `path` by `conflict`, nothing is
+/// committed on a `dry_run`.
+pub async fn import(
+    db: &DatabaseConnection,
+    path: &Path,
+    conflict: Conflict,
+    dry_run: bool,
+) -> anyhow::Result<Report> {
+    let format = Format::of(path)?;
+    let manifest = check_manifest(path)?;
+    let mut report = Report {
+        conflict,
+        dry_run,
+        verified: manifest.is_some(),
+        ..Report::default()
+    };
+    let exported_at = manifest.map(|m| m.exported_at);
+    let txn = db.begin().await?;
+    let mut batch = Vec::new();
+    for row in read(format, path)? {
+        let (line, fields) = row?;
+        report.rows += 1;
+        let fields = match fields {
+            Ok(fields) => fields,
+            Err(e) => {
+                report.errors.push(RowError {
+                    line,
+                    user_id: None,
+                    problems: vec![e],
+                });
+                continue;
+            }
+        };
+        match model(&fields, exported_at) {
+            Ok(model) => batch.push(model),
+            Err(problems) => report.errors.push(RowError {
+                line,
+                user_id: fields
+                    .get(Column::UserId.as_str())
+                    .cloned()
+                    .flatten(),
+                problems,
+            }),
+        }
+        if batch.len() >= BATCH_ROWS {
+            report.written +=
+                write(&txn, &mut batch, conflict).await?;
+        }
+    }
+    report.written +=
+        write(&txn, &mut batch, conflict).await?;
+    report.kept = report.rows
+        - report.errors.len() as u64
+        - report.written;
+    if dry_run {
+        txn.rollback().await?;
+    } else {
+        txn.commit().await?;
+    }
+    log::info!(
+        "{}{}: {} rows, {} written, {} kept, {} errors",
+        if dry_run { "dry run of " } else { "" },
+        path.display(),
+        report.rows,
+        report.written,
+        report.kept,
+        report.errors.len()
+    );
+    Ok(report)
+}
+
+/// Record the history of `batch`, then upsert and empty it. The last
+/// row of a `user_id` wins.
+async fn write(
+    txn: &DatabaseTransaction,
+    batch: &mut Vec<Model>,
+    conflict: Conflict,
+) -> Result<u64, DbErr> {
+    record_history(txn, batch).await?;
+    let models =
+        to_db::last_per_key(std::mem::take(batch), |m| {
+            m.user_id.clone()
+        });
+    to_db::upsert_with(txn, models, conflict.on_conflict())
+        .await
+}
+
+/// `models` in the history table, oldest first so that the newest
+/// identity of an account is seen last. Rows without a `last_seen_at`
+/// are seen now.
+async fn record_history(
+    txn: &DatabaseTransaction,
+    models: &[Model],
+) -> Result<(), DbErr> {
+    let now = chrono::Utc::now();
+    let mut seen: Vec<(DateTimeUtc, &Model)> = models
+        .iter()
+        .map(|m| (m.last_seen_at.unwrap_or(now), m))
+        .collect();
+    seen.sort_by_key(|(at, _)| *at);
+    for seen in seen.chunk_by(|a, b| a.0 == b.0) {
+        history::record(
+            txn,
+            seen.iter().map(|(_, model)| *model),
+            seen[0].0,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// The manifest next to `path`, if any, once `path` matches its
+/// checksum. A schema other than the current one is only logged, the
+/// columns of older ones still parse.
+fn check_manifest(
+    path: &Path,
+) -> anyhow::Result<Option<Manifest>> {
+    let manifest_path = export::manifest_path(path);
+    if !manifest_path.exists() {
+        return Ok(None);
+    }
+    let manifest: Manifest = serde_json::from_str(
+        &std::fs::read_to_string(&manifest_path)?,
+    )?;
+    let mut sha256 = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut sha256)?;
+    anyhow::ensure!(
+        format!("{:x}", sha256.finalize())
+            == manifest.sha256,
+        "{} does not match the checksum of {}",
+        path.display(),
+        manifest_path.display()
+    );
+    if manifest.schema_version != migration::version() {
+        log::warn!(
+            "{} was exported at schema {}, not {}",
+            path.display(),
+            manifest.schema_version,
+            migration::version()
+        );
+    }
+    Ok(Some(manifest))
+}
+
+/// a row by column name, `None` for NULL or an empty CSV field
+type Fields = HashMap<String, Option<String>>;
+/// line and fields of a row, or why it is not one
+type Line = (u64, Result<Fields, String>);
+
+/// rows of `path`, an error only when it can not be read at all
+fn read(
+    format: Format,
+    path: &Path,
+) -> anyhow::Result<
+    Box<dyn Iterator<Item = anyhow::Result<Line>>>,
+> {
+    match format {
+        Format::Csv => {
+            let mut reader = csv::Reader::from_path(path)?;
+            let headers = reader.headers()?.clone();
+            Ok(Box::new(reader.into_records().map(
+                move |record| csv_row(&headers, record),
+            )))
+        }
+        Format::Jsonl => {
+            let lines =
+                BufReader::new(File::open(path)?).lines();
+            Ok(Box::new(
+                lines
+                    .zip(1..)
+                    .filter(|(line, _)| {
+                        line.as_ref()
+                            .map_or(true, |l| !l.trim().is_empty())
+                    })
+                    .map(|(line, n)| -> anyhow::Result<Line> {
+                        Ok((n, json_fields(&line?)))
+                    }),
+            ))
+        }
+        Format::Parquet => {
+            anyhow::bail!(
+                "import reads .csv and .jsonl files"
+            )
+        }
+    }
+}
+
+fn csv_row(
+    headers: &csv::StringRecord,
+    record: csv::Result<csv::StringRecord>,
+) -> anyhow::Result<Line> {
+    let line = |position: Option<&csv::Position>| {
+        position.map_or(0, |p| p.line())
+    };
+    match record {
+        Ok(record) => {
+            let fields = headers
+                .iter()
+                .zip(record.iter())
+                .map(|(column, value)| {
+                    let value = (!value.is_empty())
+                        .then(|| value.to_string());
+                    (column.to_string(), value)
+                })
+                .collect();
+            Ok((line(record.position()), Ok(fields)))
+        }
+        Err(e) if e.is_io_error() => Err(e.into()),
+        Err(e) => {
+            Ok((line(e.position()), Err(e.to_string())))
+        }
+    }
+}
+
+fn json_fields(line: &str) -> Result<Fields, String> {
+    use serde_json::Value;
+
+    let object = match serde_json::from_str::<Value>(line) {
+        Ok(Value::Object(object)) => object,
+        Ok(_) => {
+            return Err("not a JSON object".to_string());
+        }
+        Err(e) => return Err(e.to_string()),
+    };
+    Ok(object
+        .into_iter()
+        .map(|(column, value)| {
+            let value = match value {
+                Value::Null => None,
+                Value::String(s) => Some(s),
+                other => Some(other.to_string()),
+            };
+            (column, value)
+        })
+        .collect())
+}
+
+/// `fields` as a valid row of astroturfers_x, or what is wrong with it.
+/// Rows of schemas before `last_seen_at` were seen by `exported_at`.
+fn model(
+    fields: &Fields,
+    exported_at: Option<DateTimeUtc>,
+) -> Result<Model, Vec<String>> {
+    let mut row = Row {
+        fields,
+        problems: Vec::new(),
+    };
+    let mut model = Model {
+        user_id: row.text(Column::UserId),
+        name: row.text(Column::Name),
+        handle: row.text(Column::Handle),
+        profile_url: row.text(Column::ProfileUrl),
+        avatar: row.text(Column::Avatar),
+        register_time: row.parse(Column::RegisterTime),
+        region: row.text(Column::Region),
+        source: row.text(Column::Source),
+        changed_name_count: row
+            .required(Column::ChangedNameCount),
+        first_seen_run: None,
+        last_seen_run: None,
+        page_url: row.text(Column::PageUrl),
+        removed_at: row.parse(Column::RemovedAt),
+        id_created_at: row.parse(Column::IdCreatedAt),
+        last_seen_at: row
+            .parse(Column::LastSeenAt)
+            .or(exported_at),
+    };
+    normalize::normalize(&mut model);
+    let mut problems = row.problems;
+    problems.extend(validate::problems(&model));
+    if problems.is_empty() {
+        Ok(model)
+    } else {
+        Err(problems)
+    }
+}
+
+/// the columns of one row, collecting the problems of all of them
+struct Row<'a> {
+    fields: &'a Fields,
+    problems: Vec<String>,
+}
+
+impl Row<'_> {
+    /// a NOT NULL text column, NULL reads as empty
+    fn text(&mut self, column: Column) -> String {
+        match self.fields.get(column.as_str()) {
+            Some(value) => {
+                value.clone().unwrap_or_default()
+            }
+            None => {
+                self.problems.push(format!(
+                    "no {}",
+                    column.as_str()
+                ));
+                String::new()
+            }
+        }
+    }
+
+    /// a nullable column, missing from files of older schemas
+    fn parse<T>(&mut self, column: Column) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let value =
+            self.fields.get(column.as_str())?.as_deref()?;
+        match value.parse() {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.problems.push(format!(
+                    "{} {:?}: {}",
+                    column.as_str(),
+                    value,
+                    e
+                ));
+                None
+            }
+        }
+    }
+
+    /// a NOT NULL column that is not text
+    fn required<T>(&mut self, column: Column) -> T
+    where
+        T: FromStr + Default,
+        T::Err: Display,
+    {
+        if !matches!(
+            self.fields.get(column.as_str()),
+            Some(Some(_))
+        ) {
+            self.problems
+                .push(format!("no {}", column.as_str()));
+        }
+        self.parse(column).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::db::test_db::TestDb;
+    use super::super::export::Filter;
+    use super::*;
+    use sea_orm::QueryOrder;
+    use std::path::PathBuf;
+
+    /// an account last seen by run `run`, `run` days after 2023-11-01
+    fn account(
+        user_id: &str,
+        name: &str,
+        run: i64,
+    ) -> Model {
+        Model {
+            name: name.to_string(),
+            handle: format!("h{}", &user_id[..8]),
+            profile_url: format!(
+                "https://x.com/h{}",
+                &user_id[..8]
+            ),
+            register_time: Date::from_ymd_opt(2024, 9, 2),
+            region: "中国".to_string(),
+            changed_name_count: 2,
+            first_seen_run: Some(1),
+            last_seen_run: Some(run),
+            last_seen_at: chrono::DateTime::from_timestamp(
+                1_698_796_800 + run * 86_400,
+                0,
+            ),
+            ..Model::test_account(user_id)
+        }
+    }
+
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "import_{}_{}",
+            std::process::id(),
+            name
+        ))
+    }
+
+    async fn stored(
+        db: &DatabaseConnection,
+    ) -> anyhow::Result<Vec<Model>> {
+        Ok(to_db::Entity::find()
+            .order_by_asc(Column::UserId)
+            .all(db)
+            .await?)
+    }
+
+    /// `models` exported to `name` with a manifest
+    async fn dataset(
+        name: &str,
+        models: Vec<Model>,
+    ) -> anyhow::Result<PathBuf> {
+        let source = TestDb::new().await?;
+        to_db::upsert(&source.db, models).await?;
+        let path = temp_file(name);
+        let filter = Filter {
+            include_removed: true,
+            ..Filter::default()
+        };
+        export::export(&source.db, &filter, &path).await?;
+        Ok(path)
+    }
+
+    fn remove(path: &Path) -> io::Result<()> {
+        let _ = std::fs::remove_file(
+            export::manifest_path(path),
+        );
+        std::fs::remove_file(path)
+    }
+
+    #[tokio::test]
+    async fn round_trip() -> anyhow::Result<()> {
+        let mut models = vec![
+            account(
+                "1830540823630675969",
+                "say \"hi\", ok",
+                3,
+            ),
+            account("1672931804427603968", "", 4),
+        ];
+        models[1].removed_at =
+            chrono::DateTime::from_timestamp(
+                1_700_000_000,
+                0,
+            );
+        models[1].register_time = None;
+        for name in ["round_trip.csv", "round_trip.jsonl"] {
+            let path =
+                dataset(name, models.clone()).await?;
+            let test = TestDb::new().await?;
+            let report = import(
+                &test.db,
+                &path,
+                Conflict::default(),
+                false,
+            )
+            .await?;
+            assert!(report.verified);
+            assert_eq!(report.rows, 2);
+            assert_eq!(report.written, 2);
+            assert!(report.errors.is_empty());
+
+            // run ids of the source mean nothing here
+            let mut expected: Vec<Model> = models
+                .iter()
+                .map(|model| Model {
+                    first_seen_run: None,
+                    last_seen_run: None,
+                    ..model.clone()
+                })
+                .collect();
+            expected
+                .sort_by(|a, b| a.user_id.cmp(&b.user_id));
+            assert_eq!(stored(&test.db).await?, expected);
+            remove(&path)?;
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn conflict_strategies() -> anyhow::Result<()> {
+        let path = dataset(
+            "conflicts.jsonl",
+            vec![
+                account("1830540823630675969", "older", 3),
+                account("1672931804427603968", "newer", 7),
+                account("1544278901236539392", "new", 1),
+            ],
+        )
+        .await?;
+        let cases = [
+            // in user_id order, the new account first
+            (
+                Conflict::KeepNewest,
+                ["new", "newer", "stored"],
+            ),
+            (
+                Conflict::KeepExisting,
+                ["new", "stored", "stored"],
+            ),
+            (
+                Conflict::Overwrite,
+                ["new", "newer", "older"],
+            ),
+        ];
+        for (conflict, names) in cases {
+            let test = TestDb::new().await?;
+            to_db::upsert(
+                &test.db,
+                vec![
+                    account(
+                        "1830540823630675969",
+                        "stored",
+                        5,
+                    ),
+                    account(
+                        "1672931804427603968",
+                        "stored",
+                        5,
+                    ),
+                ],
+            )
+            .await?;
+
+            let dry =
+                import(&test.db, &path, conflict, true)
+                    .await?;
+            let names_now: Vec<String> = stored(&test.db)
+                .await?
+                .into_iter()
+                .map(|m| m.name)
+                .collect();
+            assert_eq!(names_now, ["stored", "stored"]);
+
+            let report =
+                import(&test.db, &path, conflict, false)
+                    .await?;
+            assert_eq!(report.written, dry.written);
+            let imported: Vec<String> = stored(&test.db)
+                .await?
+                .into_iter()
+                .map(|m| m.name)
+                .collect();
+            assert_eq!(imported, names, "{:?}", conflict);
+            let runs: Vec<Option<i64>> = stored(&test.db)
+                .await?
+                .into_iter()
+                .map(|m| m.last_seen_run)
+                .collect();
+            assert_eq!(runs, [None, Some(5), Some(5)]);
+            assert_eq!(
+                report.written + report.kept,
+                3,
+                "{:?}",
+                conflict
+            );
+        }
+        remove(&path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn row_errors() -> anyhow::Result<()> {
+        let test = TestDb::new().await?;
+        let path = temp_file("errors.csv");
+        let header = to_db::Column::iter()
+            .map(|c| c.as_str())
+            .collect::<Vec<_>>()
+            .join(",");
+        let valid = "1830540823630675969,a,@Ynhu434128,\
+            https://x.com/Ynhu434128,https://pbs.twimg.com/a.jpg,\
+            2024-09-02,,,3,1,1,https://example.com/,,,";
+        let rows = [
+            header.as_str(),
+            valid,
+            // user id, date and count
+            "12ab,a,b,https://x.com/b,https://pbs.twimg.com/a.jpg,\
+             2024-13-01,,,,1,1,https://example.com/,,,",
+            // a field short
+            "1,a,b",
+        ];
+        std::fs::write(&path, rows.join("\n"))?;
+
+        let report = import(
+            &test.db,
+            &path,
+            Conflict::Overwrite,
+            false,
+        )
+        .await?;
+        assert!(!report.verified);
+        assert_eq!(report.rows, 3);
+        assert_eq!(report.written, 1);
+        let lines: Vec<u64> =
+            report.errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, [3, 4]);
+        assert_eq!(
+            report.errors[0].user_id.as_deref(),
+            Some("12ab")
+        );
+        let problems = &report.errors[0].problems;
+        assert_eq!(problems.len(), 3);
+        assert!(problems[0].starts_with("register_time"));
+        assert_eq!(problems[1], "no changed_name_count");
+        assert!(problems[2].starts_with("user_id"));
+
+        // normalized like a crawled row
+        let row = &stored(&test.db).await?[0];
+        assert_eq!(row.handle, "ynhu434128");
+        assert_eq!(
+            row.profile_url,
+            "https://x.com/ynhu434128"
+        );
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn imported_rows_get_their_history()
+    -> anyhow::Result<()> {
+        let user_id = "1830540823630675969";
+        let path = dataset(
+            "history.jsonl",
+            vec![account(user_id, "imported", 3)],
+        )
+        .await?;
+        let test = TestDb::new().await?;
+        let existing = account(user_id, "stored", 5);
+        history::record(
+            &test.db,
+            [&existing],
+            existing.last_seen_at.expect("seen"),
+        )
+        .await?;
+        to_db::upsert(&test.db, vec![existing]).await?;
+
+        // kept by the table, still seen before it
+        let report = import(
+            &test.db,
+            &path,
+            Conflict::KeepNewest,
+            false,
+        )
+        .await?;
+        assert_eq!(report.kept, 1);
+        let names: Vec<String> =
+            history::timeline(&test.db, user_id)
+                .await?
+                .into_iter()
+                .map(|identity| identity.name)
+                .collect();
+        assert_eq!(names, ["imported", "stored"]);
+        remove(&path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn checksum_mismatch() -> anyhow::Result<()> {
+        let path = dataset(
+            "tampered.csv",
+            vec![account("1830540823630675969", "a", 1)],
+        )
+        .await?;
+        let mut csv = std::fs::read_to_string(&path)?;
+        csv = csv.replace("\"a\"", "\"b\"");
+        std::fs::write(&path, csv)?;
+
+        let test = TestDb::new().await?;
+        let imported = import(
+            &test.db,
+            &path,
+            Conflict::default(),
+            true,
+        )
+        .await;
+        assert!(imported.is_err());
+        remove(&path)?;
+        Ok(())
+    }
+}
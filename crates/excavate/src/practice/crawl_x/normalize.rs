@@ -0,0 +1,16 @@
+//! canonical forms of X identities, so rows of different sources and
+//! crawls join and dedup on the same values
+use super::to_db::Model;
+use utils::tools::x_identity::{
+    avatar_url, handle, profile_url,
+};
+
+/// canonical `handle`, `profile_url` and `avatar` of `model`, a
+/// profile url that is not on X is left for validation to catch
+pub fn normalize(model: &mut Model) {
+    model.handle = handle(&model.handle);
+    if let Some(url) = profile_url(&model.profile_url) {
+        model.profile_url = url;
+    }
+    model.avatar = avatar_url(&model.avatar);
+}
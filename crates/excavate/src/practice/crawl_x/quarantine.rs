@@ -0,0 +1,143 @@
+//! parsed rows that failed `validate`, kept for review instead of
+//! reaching astroturfers_x
+use super::to_db;
+use sea_orm::ActiveValue::{NotSet, Set};
+use sea_orm::entity::prelude::*;
+use sea_orm::{DbBackend, Insert, Iterable};
+
+#[derive(
+    Clone, Debug, PartialEq, Eq, DeriveEntityModel,
+)]
+#[sea_orm(table_name = "quarantine")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub run_id: i64,
+    /// page the row was parsed from
+    pub page_url: String,
+    pub user_id: String,
+    pub name: String,
+    pub handle: String,
+    pub profile_url: String,
+    pub avatar: String,
+    /// every failed check, one per line
+    pub reasons: String,
+    pub quarantined_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// keep `rejected` rows of run `run_id` with the reasons they failed
+pub async fn store<C: ConnectionTrait>(
+    db: &C,
+    run_id: i64,
+    at: DateTimeUtc,
+    rejected: &[(to_db::Model, Vec<String>)],
+) -> Result<(), DbErr> {
+    let backend = db.get_database_backend();
+    for insert in inserts(backend, run_id, at, rejected) {
+        insert.exec(db).await?;
+    }
+    Ok(())
+}
+
+/// the inserts of `store`, under the parameter limit of `backend`
+pub fn inserts(
+    backend: DbBackend,
+    run_id: i64,
+    at: DateTimeUtc,
+    rejected: &[(to_db::Model, Vec<String>)],
+) -> Vec<Insert<ActiveModel>> {
+    let per_statement = to_db::rows_per_statement(
+        backend,
+        Column::iter().count(),
+    );
+    rejected
+        .chunks(per_statement)
+        .map(|chunk| {
+            Entity::insert_many(chunk.iter().map(
+                |(model, reasons)| {
+                    active_model(run_id, at, model, reasons)
+                },
+            ))
+        })
+        .collect()
+}
+
+fn active_model(
+    run_id: i64,
+    at: DateTimeUtc,
+    m: &to_db::Model,
+    reasons: &[String],
+) -> ActiveModel {
+    ActiveModel {
+        id: NotSet,
+        run_id: Set(run_id),
+        page_url: Set(m.page_url.clone()),
+        user_id: Set(m.user_id.clone()),
+        name: Set(m.name.clone()),
+        handle: Set(m.handle.clone()),
+        profile_url: Set(m.profile_url.clone()),
+        avatar: Set(m.avatar.clone()),
+        reasons: Set(reasons.join("\n")),
+        quarantined_at: Set(at),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::bulk::{BulkWriter, PageRows};
+    use super::super::crawl_run;
+    use super::super::db::test_db::TestDb;
+    use super::*;
+
+    #[tokio::test]
+    async fn invalid_row_is_quarantined()
+    -> anyhow::Result<()> {
+        let test = TestDb::new().await?;
+        let db = &test.db;
+        let url = "https://example.com/page2.html";
+        let broken = to_db::Model {
+            handle: "has space".to_string(),
+            page_url: url.to_string(),
+            ..to_db::Model::test_account("2")
+        };
+        let run = crawl_run::start(db).await?;
+        let mut writer = BulkWriter::new(db);
+        writer.push(PageRows {
+            url: url.to_string(),
+            run_id: run.id,
+            seen_at: chrono::Utc::now(),
+            models: vec![
+                to_db::Model::test_account("1"),
+                broken,
+            ],
+            validators: None,
+        });
+        assert_eq!(writer.flush().await?, 1);
+
+        let rows = Entity::find().all(db).await?;
+        assert_eq!(rows.len(), 1);
+        let row = &rows[0];
+        assert_eq!(
+            (row.run_id, row.user_id.as_str()),
+            (run.id, "2")
+        );
+        assert_eq!(row.page_url, url);
+        assert_eq!(row.handle, "has space");
+        assert_eq!(
+            row.reasons,
+            "handle \"has space\" is not an X handle"
+        );
+        assert!(
+            to_db::Entity::find_by_id("2")
+                .one(db)
+                .await?
+                .is_none()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,576 @@
+//! analytics of astroturfers_x as plain text, JSON or one HTML page
+//!
+//! The rows of an `export::Filter` are streamed once and counted here:
+//! registrations per day or week, the spread of `changed_name_count`,
+//! regions and sources, and the top accounts. The queries of
+//! `check.sql` stay for poking around.
+use super::export::Filter;
+use super::to_db::{Column, Entity, Model};
+use chrono::{Datelike, Days};
+use futures::TryStreamExt;
+use sea_orm::QueryOrder;
+use sea_orm::entity::prelude::*;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::str::FromStr;
+
+mod html;
+
+/// accounts in each top list unless told otherwise
+pub const DEFAULT_TOP: usize = 20;
+/// characters of the longest text bar
+const BAR_WIDTH: u64 = 40;
+
+/// buckets of the registration histogram
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Serialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Interval {
+    Day,
+    /// from Monday
+    #[default]
+    Week,
+}
+
+impl Interval {
+    /// first day of the bucket of `date`
+    fn start(self, date: Date) -> Date {
+        match self {
+            Self::Day => date,
+            Self::Week => {
+                let into_week =
+                    date.weekday().num_days_from_monday();
+                date - Days::new(into_week.into())
+            }
+        }
+    }
+
+    fn days(self) -> Days {
+        match self {
+            Self::Day => Days::new(1),
+            Self::Week => Days::new(7),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Day => "day",
+            Self::Week => "week",
+        }
+    }
+}
+
+impl FromStr for Interval {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "day" => Ok(Self::Day),
+            "week" => Ok(Self::Week),
+            _ => anyhow::bail!("{} is not day or week", s),
+        }
+    }
+}
+
+/// how `Report::render` writes a report
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Output {
+    #[default]
+    Text,
+    Json,
+    Html,
+}
+
+impl FromStr for Output {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "html" => Ok(Self::Html),
+            _ => anyhow::bail!(
+                "{} is not text, json or html",
+                s
+            ),
+        }
+    }
+}
+
+/// accounts with one value of a column
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Count<K> {
+    pub key: K,
+    pub count: u64,
+}
+
+/// an account of a top list
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Account {
+    pub user_id: String,
+    pub name: String,
+    pub handle: String,
+    pub register_time: Option<Date>,
+    pub changed_name_count: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub generated_at: DateTimeUtc,
+    pub filter: Filter,
+    pub interval: Interval,
+    pub accounts: u64,
+    /// accounts without `register_time`, not in `registrations`
+    pub unregistered: u64,
+    /// accounts per bucket from the first to the last, empty ones
+    /// included
+    pub registrations: Vec<Count<Date>>,
+    /// accounts per `changed_name_count`, ascending
+    pub renames: Vec<Count<u32>>,
+    /// accounts per region, most first, `""` for none
+    pub regions: Vec<Count<String>>,
+    /// accounts per source, most first, `""` for none
+    pub sources: Vec<Count<String>>,
+    /// most `changed_name_count` first
+    pub most_renamed: Vec<Account>,
+    /// latest `register_time` first
+    pub newest: Vec<Account>,
+}
+
+/// Count the rows of `filter`, registrations by `interval` and `top`
+/// accounts per list.
+pub async fn report(
+    db: &DatabaseConnection,
+    filter: &Filter,
+    interval: Interval,
+    top: usize,
+) -> anyhow::Result<Report> {
+    let mut tally = Tally::new(interval, top);
+    let mut rows = std::pin::pin!(
+        Entity::find()
+            .filter(filter.condition())
+            .order_by_asc(Column::UserId)
+            .stream(db)
+            .await?
+    );
+    while let Some(model) = rows.try_next().await? {
+        tally.add(model);
+    }
+    Ok(tally.report(filter))
+}
+
+/// what the rows counted so far add up to
+struct Tally {
+    interval: Interval,
+    top: usize,
+    accounts: u64,
+    unregistered: u64,
+    registrations: BTreeMap<Date, u64>,
+    renames: BTreeMap<u32, u64>,
+    regions: HashMap<String, u64>,
+    sources: HashMap<String, u64>,
+    most_renamed: Vec<Account>,
+    newest: Vec<Account>,
+}
+
+impl Tally {
+    fn new(interval: Interval, top: usize) -> Self {
+        Self {
+            interval,
+            top,
+            accounts: 0,
+            unregistered: 0,
+            registrations: BTreeMap::new(),
+            renames: BTreeMap::new(),
+            regions: HashMap::new(),
+            sources: HashMap::new(),
+            most_renamed: Vec::new(),
+            newest: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, model: Model) {
+        self.accounts += 1;
+        *self
+            .renames
+            .entry(model.changed_name_count)
+            .or_default() += 1;
+        *self.regions.entry(model.region).or_default() += 1;
+        *self.sources.entry(model.source).or_default() += 1;
+        let account = Account {
+            user_id: model.user_id,
+            name: model.name,
+            handle: model.handle,
+            register_time: model.register_time,
+            changed_name_count: model.changed_name_count,
+        };
+        match account.register_time {
+            Some(date) => {
+                *self
+                    .registrations
+                    .entry(self.interval.start(date))
+                    .or_default() += 1;
+                self.newest.push(account.clone());
+            }
+            None => self.unregistered += 1,
+        }
+        self.most_renamed.push(account);
+        // sorting now and then keeps the lists short
+        if self.most_renamed.len() > 2 * self.top.max(1) {
+            keep_top(
+                &mut self.most_renamed,
+                self.top,
+                |a| a.changed_name_count,
+            );
+        }
+        if self.newest.len() > 2 * self.top.max(1) {
+            keep_top(&mut self.newest, self.top, |a| {
+                a.register_time
+            });
+        }
+    }
+
+    fn report(mut self, filter: &Filter) -> Report {
+        keep_top(&mut self.most_renamed, self.top, |a| {
+            a.changed_name_count
+        });
+        keep_top(&mut self.newest, self.top, |a| {
+            a.register_time
+        });
+        let mut registrations = Vec::new();
+        if let (Some((&first, _)), Some((&last, _))) = (
+            self.registrations.first_key_value(),
+            self.registrations.last_key_value(),
+        ) {
+            let mut start = first;
+            while start <= last {
+                registrations.push(Count {
+                    key: start,
+                    count: self
+                        .registrations
+                        .get(&start)
+                        .copied()
+                        .unwrap_or(0),
+                });
+                start = start + self.interval.days();
+            }
+        }
+        Report {
+            generated_at: chrono::Utc::now(),
+            filter: filter.clone(),
+            interval: self.interval,
+            accounts: self.accounts,
+            unregistered: self.unregistered,
+            registrations,
+            renames: self
+                .renames
+                .into_iter()
+                .map(|(key, count)| Count { key, count })
+                .collect(),
+            regions: most_first(self.regions),
+            sources: most_first(self.sources),
+            most_renamed: self.most_renamed,
+            newest: self.newest,
+        }
+    }
+}
+
+/// the `n` accounts of the greatest `key`, ties by `user_id`
+fn keep_top<K: Ord>(
+    accounts: &mut Vec<Account>,
+    n: usize,
+    key: impl Fn(&Account) -> K,
+) {
+    accounts.sort_by(|a, b| {
+        key(b)
+            .cmp(&key(a))
+            .then_with(|| a.user_id.cmp(&b.user_id))
+    });
+    accounts.truncate(n);
+}
+
+/// `counts` by count, ties by key
+fn most_first(
+    counts: HashMap<String, u64>,
+) -> Vec<Count<String>> {
+    let mut counts: Vec<Count<String>> = counts
+        .into_iter()
+        .map(|(key, count)| Count { key, count })
+        .collect();
+    counts.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| a.key.cmp(&b.key))
+    });
+    counts
+}
+
+/// `key` for humans, `(none)` for an empty one
+fn label(key: &impl Display) -> String {
+    match key.to_string() {
+        key if key.is_empty() => "(none)".to_string(),
+        key => key,
+    }
+}
+
+impl Report {
+    pub fn render(
+        &self,
+        output: Output,
+    ) -> anyhow::Result<String> {
+        Ok(match output {
+            Output::Text => self.text(),
+            Output::Json => {
+                serde_json::to_string_pretty(self)?
+            }
+            Output::Html => html::page(self),
+        })
+    }
+
+    fn text(&self) -> String {
+        let mut out = format!(
+            "astroturfers_x, {} accounts, {} without register_time\n\
+             generated {}\n",
+            self.accounts,
+            self.unregistered,
+            self.generated_at.to_rfc3339()
+        );
+        text_bars(
+            &mut out,
+            &format!(
+                "registrations per {}",
+                self.interval.name()
+            ),
+            &self.registrations,
+        );
+        text_bars(
+            &mut out,
+            "changed_name_count",
+            &self.renames,
+        );
+        text_bars(&mut out, "region", &self.regions);
+        text_bars(&mut out, "source", &self.sources);
+        text_accounts(
+            &mut out,
+            "most renamed",
+            &self.most_renamed,
+        );
+        text_accounts(&mut out, "newest", &self.newest);
+        out
+    }
+}
+
+/// a section of `counts` with bars scaled to the largest
+fn text_bars<K: Display>(
+    out: &mut String,
+    title: &str,
+    counts: &[Count<K>],
+) {
+    out.push_str(&format!("\n{}\n", title));
+    let labels: Vec<String> =
+        counts.iter().map(|c| label(&c.key)).collect();
+    let width = labels
+        .iter()
+        .map(|l| l.chars().count())
+        .max()
+        .unwrap_or(0);
+    let max =
+        counts.iter().map(|c| c.count).max().unwrap_or(0);
+    for (label, count) in labels.iter().zip(counts) {
+        let bar =
+            (count.count * BAR_WIDTH).div_ceil(max.max(1));
+        let line = format!(
+            "{}{} {:>7} {}",
+            label,
+            " ".repeat(width - label.chars().count()),
+            count.count,
+            "#".repeat(bar as usize)
+        );
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+}
+
+fn text_accounts(
+    out: &mut String,
+    title: &str,
+    accounts: &[Account],
+) {
+    out.push_str(&format!("\n{}\n", title));
+    for account in accounts {
+        out.push_str(&format!(
+            "{:<20} {:<16} {:>3} {:<10} {}\n",
+            account.user_id,
+            account.handle,
+            account.changed_name_count,
+            account
+                .register_time
+                .map(|date| date.to_string())
+                .unwrap_or_default(),
+            account.name
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::db::test_db::TestDb;
+    use super::super::to_db;
+    use super::*;
+
+    fn account(
+        user_id: &str,
+        registered: Option<(i32, u32, u32)>,
+        changed_name_count: u32,
+        region: &str,
+    ) -> Model {
+        Model {
+            name: format!("<b>{}</b>", user_id),
+            handle: format!("h{}", user_id),
+            profile_url: format!(
+                "https://x.com/h{}",
+                user_id
+            ),
+            register_time: registered.and_then(
+                |(y, m, d)| Date::from_ymd_opt(y, m, d),
+            ),
+            region: region.to_string(),
+            source: "Twitter Web App".to_string(),
+            changed_name_count,
+            ..Model::test_account(user_id)
+        }
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> Date {
+        Date::from_ymd_opt(y, m, d).expect("a date")
+    }
+
+    fn tallied(top: usize) -> Report {
+        let mut tally = Tally::new(Interval::Week, top);
+        for model in [
+            // Wednesday and Sunday of the same week
+            account("1", Some((2024, 9, 4)), 3, "中国"),
+            account("2", Some((2024, 9, 8)), 0, "中国"),
+            account("3", Some((2024, 9, 23)), 5, ""),
+            account("4", None, 5, "美国"),
+        ] {
+            tally.add(model);
+        }
+        tally.report(&Filter::default())
+    }
+
+    #[test]
+    fn counts() {
+        let report = tallied(2);
+        assert_eq!(report.accounts, 4);
+        assert_eq!(report.unregistered, 1);
+        let weeks: Vec<(Date, u64)> = report
+            .registrations
+            .iter()
+            .map(|c| (c.key, c.count))
+            .collect();
+        assert_eq!(
+            weeks,
+            [
+                (date(2024, 9, 2), 2),
+                (date(2024, 9, 9), 0),
+                (date(2024, 9, 16), 0),
+                (date(2024, 9, 23), 1),
+            ]
+        );
+        let renames: Vec<(u32, u64)> = report
+            .renames
+            .iter()
+            .map(|c| (c.key, c.count))
+            .collect();
+        assert_eq!(renames, [(0, 1), (3, 1), (5, 2)]);
+        assert_eq!(
+            report.regions,
+            [
+                Count {
+                    key: "中国".to_string(),
+                    count: 2
+                },
+                Count {
+                    key: String::new(),
+                    count: 1
+                },
+                Count {
+                    key: "美国".to_string(),
+                    count: 1
+                },
+            ]
+        );
+        let ids = |accounts: &[Account]| -> Vec<String> {
+            accounts
+                .iter()
+                .map(|a| a.user_id.clone())
+                .collect()
+        };
+        assert_eq!(ids(&report.most_renamed), ["3", "4"]);
+        assert_eq!(ids(&report.newest), ["3", "2"]);
+    }
+
+    #[test]
+    fn renders() -> anyhow::Result<()> {
+        let report = tallied(DEFAULT_TOP);
+        let text = report.render(Output::Text)?;
+        assert!(text.contains("\n2024-09-02       2 ####"));
+        assert!(text.contains("\n(none)       1 #"));
+        assert!(text.contains("\n2024-09-09       0\n"));
+
+        let json: serde_json::Value = serde_json::from_str(
+            &report.render(Output::Json)?,
+        )?;
+        assert_eq!(json["accounts"], 4);
+        assert_eq!(
+            json["registrations"][0]["key"],
+            "2024-09-02"
+        );
+
+        let html = report.render(Output::Html)?;
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("&lt;b&gt;1&lt;/b&gt;"));
+        assert!(!html.contains("<b>1</b>"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn from_db() -> anyhow::Result<()> {
+        let test = TestDb::new().await?;
+        to_db::upsert(
+            &test.db,
+            vec![
+                account(
+                    "1830540823630675969",
+                    Some((2024, 9, 2)),
+                    3,
+                    "中国",
+                ),
+                account(
+                    "1672931804427603968",
+                    None,
+                    1,
+                    "中国",
+                ),
+            ],
+        )
+        .await?;
+        let filter = Filter {
+            min_changed_name_count: Some(2),
+            ..Filter::default()
+        };
+        let report =
+            report(&test.db, &filter, Interval::Day, 5)
+                .await?;
+        assert_eq!(report.accounts, 1);
+        assert_eq!(report.registrations.len(), 1);
+        assert_eq!(
+            report.most_renamed[0].user_id,
+            "1830540823630675969"
+        );
+        Ok(())
+    }
+}
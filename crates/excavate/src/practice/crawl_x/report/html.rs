@@ -0,0 +1,145 @@
+//! a report as one HTML page, the charts are inline SVG and CSS so it
+//! opens anywhere without network
+use super::{Account, Count, Report, label};
+use std::fmt::Display;
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+    h2{margin-top:2em}\
+    table{border-collapse:collapse}\
+    td,th{padding:2px 8px;text-align:left}\
+    td.n{text-align:right}\
+    .bar{background:#4a7ab5;height:12px}\
+    .timeline{overflow-x:auto}\
+    .timeline rect{fill:#4a7ab5}";
+/// height of the registration chart
+const CHART_HEIGHT: f64 = 160.0;
+
+pub fn page(report: &Report) -> String {
+    let mut body = format!(
+        "<h1>astroturfers_x</h1>\n<p>{} accounts, {} without \
+         register_time, generated {}</p>\n<pre>{}</pre>\n",
+        report.accounts,
+        report.unregistered,
+        report.generated_at.to_rfc3339(),
+        escape(
+            &serde_json::to_string(&report.filter)
+                .unwrap_or_default()
+        )
+    );
+    body.push_str(&format!(
+        "<h2>registrations per {}</h2>\n{}",
+        report.interval.name(),
+        timeline(&report.registrations)
+    ));
+    body.push_str(&bars(
+        "changed_name_count",
+        &report.renames,
+    ));
+    body.push_str(&bars("region", &report.regions));
+    body.push_str(&bars("source", &report.sources));
+    body.push_str(&accounts(
+        "most renamed",
+        &report.most_renamed,
+    ));
+    body.push_str(&accounts("newest", &report.newest));
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>astroturfers_x report</title>\n\
+         <style>{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        STYLE, body
+    )
+}
+
+/// a column per bucket, the count in its tooltip
+fn timeline<K: Display>(counts: &[Count<K>]) -> String {
+    let max =
+        counts.iter().map(|c| c.count).max().unwrap_or(0);
+    let width = (900.0 / counts.len().max(1) as f64)
+        .clamp(2.0, 24.0);
+    let mut svg = format!(
+        "<div class=\"timeline\"><svg width=\"{}\" height=\"{}\">\n",
+        width * counts.len() as f64,
+        CHART_HEIGHT
+    );
+    for (i, count) in counts.iter().enumerate() {
+        let height = CHART_HEIGHT * count.count as f64
+            / max.max(1) as f64;
+        svg.push_str(&format!(
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" \
+             height=\"{:.1}\"><title>{}: {}</title></rect>\n",
+            i as f64 * width,
+            CHART_HEIGHT - height,
+            (width - 1.0).max(1.0),
+            height,
+            escape(&count.key.to_string()),
+            count.count
+        ));
+    }
+    svg.push_str("</svg></div>\n");
+    svg
+}
+
+/// a table of `counts` with bars scaled to the largest
+fn bars<K: Display>(
+    title: &str,
+    counts: &[Count<K>],
+) -> String {
+    let max =
+        counts.iter().map(|c| c.count).max().unwrap_or(0);
+    let mut table =
+        format!("<h2>{}</h2>\n<table>\n", escape(title));
+    for count in counts {
+        table.push_str(&format!(
+            "<tr><td>{}</td><td class=\"n\">{}</td>\
+             <td style=\"width:300px\"><div class=\"bar\" \
+             style=\"width:{:.1}%\"></div></td></tr>\n",
+            escape(&label(&count.key)),
+            count.count,
+            100.0 * count.count as f64 / max.max(1) as f64
+        ));
+    }
+    table.push_str("</table>\n");
+    table
+}
+
+fn accounts(title: &str, accounts: &[Account]) -> String {
+    let mut table = format!(
+        "<h2>{}</h2>\n<table>\n<tr><th>user_id</th><th>handle</th>\
+         <th>changed_name_count</th><th>register_time</th>\
+         <th>name</th></tr>\n",
+        escape(title)
+    );
+    for account in accounts {
+        table.push_str(&format!(
+            "<tr><td>{}</td><td><a href=\"https://x.com/{}\">@{}</a>\
+             </td><td class=\"n\">{}</td><td>{}</td><td>{}</td></tr>\n",
+            escape(&account.user_id),
+            escape(&account.handle),
+            escape(&account.handle),
+            account.changed_name_count,
+            account
+                .register_time
+                .map(|date| date.to_string())
+                .unwrap_or_default(),
+            escape(&account.name)
+        ));
+    }
+    table.push_str("</table>\n");
+    table
+}
+
+/// `text` safe in HTML text and attribute values
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
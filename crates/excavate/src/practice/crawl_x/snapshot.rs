@@ -0,0 +1,125 @@
+//! gzip archive of every fetched page, so a parser fix never needs a
+//! re-crawl
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use sea_orm::ActiveValue::{NotSet, Set};
+use sea_orm::QueryOrder;
+use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::{Func, Query};
+use std::io::{Read, Write};
+
+#[derive(
+    Clone, Debug, PartialEq, Eq, DeriveEntityModel,
+)]
+#[sea_orm(table_name = "page_snapshots")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub url: String,
+    pub fetched_at: DateTimeUtc,
+    /// crawl run that fetched the page
+    pub run_id: i64,
+    /// gzip of the response body
+    pub html_gz: Vec<u8>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Model {
+    pub fn html(&self) -> std::io::Result<String> {
+        decompress(&self.html_gz)
+    }
+}
+
+pub fn compress(html: &str) -> std::io::Result<Vec<u8>> {
+    let mut encoder =
+        GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(html.as_bytes())?;
+    encoder.finish()
+}
+
+pub fn decompress(
+    html_gz: &[u8],
+) -> std::io::Result<String> {
+    let mut html = String::new();
+    GzDecoder::new(html_gz).read_to_string(&mut html)?;
+    Ok(html)
+}
+
+/// archive `html` of `url` as fetched now by run `run_id`
+pub async fn archive<C: ConnectionTrait>(
+    db: &C,
+    run_id: i64,
+    url: &str,
+    html: &str,
+) -> anyhow::Result<Model> {
+    let snapshot = ActiveModel {
+        id: NotSet,
+        url: Set(url.to_string()),
+        fetched_at: Set(chrono::Utc::now()),
+        run_id: Set(run_id),
+        html_gz: Set(compress(html)?),
+    }
+    .insert(db)
+    .await?;
+    Ok(snapshot)
+}
+
+/// The newest snapshot of every archived url, picked in SQL by the
+/// highest id per url, so only those blobs are read. Ids grow with
+/// `fetched_at`, stream it to hold one page at a time.
+pub fn latest_per_url() -> Select<Entity> {
+    Entity::find()
+        .filter(
+            Column::Id.in_subquery(
+                Query::select()
+                    .expr(Func::max(Expr::col(Column::Id)))
+                    .from(Entity)
+                    .group_by_col(Column::Url)
+                    .to_owned(),
+            ),
+        )
+        .order_by_asc(Column::Url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::crawl_run;
+    use super::super::db::test_db::TestDb;
+    use super::*;
+    use futures::TryStreamExt;
+
+    #[test]
+    fn round_trip() -> anyhow::Result<()> {
+        let html =
+            r#"<article class="user-card">烟火</article>"#;
+        let html_gz = compress(html)?;
+        assert_eq!(decompress(&html_gz)?, html);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_latest_per_url() -> anyhow::Result<()> {
+        let test = TestDb::new().await?;
+        let db = &test.db;
+        let run = crawl_run::start(db).await?;
+        archive(db, run.id, "https://a/", "old").await?;
+        archive(db, run.id, "https://b/", "b").await?;
+        archive(db, run.id, "https://a/", "new").await?;
+
+        let latest: Vec<Model> = latest_per_url()
+            .stream(db)
+            .await?
+            .try_collect()
+            .await?;
+        assert_eq!(latest.len(), 2);
+        assert_eq!(latest[0].url, "https://a/");
+        assert_eq!(latest[0].html()?, "new");
+        assert_eq!(latest[1].html()?, "b");
+        Ok(())
+    }
+}
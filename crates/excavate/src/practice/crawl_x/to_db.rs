@@ -1,22 +1,138 @@
 //! insert data of crawl_x into database
+use super::{crawl_run, validate};
+use crate::extract::Extract;
+use futures::TryStreamExt;
 use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::{Expr, OnConflict};
+use sea_orm::{Condition, DbBackend, Iterable, UpdateMany};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::hash::Hash;
+use utils::config::env::ENV_SETTINGS;
+use utils::tools::snowflake::parse_snowflake_time;
+use utils::tools::time::parse_date;
+
+/// a complete run never tombstones more than this share of the live
+/// accounts, a site serving half its list is a glitch, not a purge
+const MAX_TOMBSTONE_SHARE: f64 = 0.5;
+/// days `register_time` may be off `id_created_at`, the site shows
+/// dates of its own timezone
+const REGISTER_TOLERANCE_DAYS: i64 = 1;
 
 #[derive(
-    Clone, Debug, PartialEq, Eq, DeriveEntityModel,
+    Clone, Debug, PartialEq, Eq, DeriveEntityModel, Extract,
 )]
-#[sea_orm(
-    table_name = "astroturfers_x",
-    schema_name = "dev"
+#[sea_orm(table_name = "astroturfers_x")]
+#[extract(
+    container = "article.user-card",
+    meta(
+        selector = "div.user-meta span",
+        separator = "："
+    )
 )]
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
+    #[extract(
+        selector = "div.user-id",
+        trim,
+        strip_prefix = "ID: "
+    )]
     pub user_id: String,
+    #[extract(selector = "h2.user-name", trim)]
     pub name: String,
+    /// lowercase without the `@`, see `normalize`
+    #[extract(selector = "div.user-handle", trim)]
     pub handle: String,
+    /// `https://x.com/<handle>`
+    #[extract(
+        selector = ".user-avatar-wrap a",
+        attr = "href"
+    )]
     pub profile_url: String,
+    /// full size image
+    #[extract(selector = "img.user-avatar", attr = "src")]
     pub avatar: String,
-    pub register_time: String,
+    /// `注册：` as a UTC date, `None` when the card has none, a date
+    /// that does not parse rejects the card
+    #[extract(meta = "注册", with = "register_date")]
+    pub register_time: Option<Date>,
+    /// `地区：` of the card
+    #[extract(meta = "地区", default)]
+    pub region: String,
+    /// `来源：` of the card, the client used to register
+    #[extract(meta = "来源", default)]
+    pub source: String,
+    #[extract(meta = "改名次数", default)]
     pub changed_name_count: u32,
+    /// crawl run that first saw the account, `None` before run bookkeeping
+    #[extract(skip)]
+    pub first_seen_run: Option<i64>,
+    /// latest crawl run that saw the account
+    #[extract(skip)]
+    pub last_seen_run: Option<i64>,
+    /// page the row was last parsed from
+    #[extract(skip)]
+    pub page_url: String,
+    /// when the account disappeared from the site, `None` while listed
+    #[extract(skip)]
+    pub removed_at: Option<DateTimeUtc>,
+    /// creation time encoded in the snowflake `user_id`, set on upsert
+    #[extract(skip)]
+    pub id_created_at: Option<DateTimeUtc>,
+    /// when `last_seen_run` saw the account, compares rows of another
+    /// database whose run ids mean nothing here
+    #[extract(skip)]
+    pub last_seen_at: Option<DateTimeUtc>,
+}
+
+impl Model {
+    /// the scraped `register_time` disagrees with the snowflake
+    pub fn register_mismatch(&self) -> bool {
+        match (self.register_time, self.id_created_at) {
+            (Some(date), Some(created)) => {
+                (date - created.date_naive())
+                    .num_days()
+                    .abs()
+                    > REGISTER_TOLERANCE_DAYS
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Model {
+    /// a valid account no run has stamped yet, tests change what they
+    /// need with `..Model::test_account(user_id)`
+    pub fn test_account(user_id: &str) -> Self {
+        Self {
+            user_id: user_id.to_string(),
+            name: "烟火".to_string(),
+            handle: "ynhu434128".to_string(),
+            profile_url: "https://x.com/ynhu434128".to_string(),
+            avatar: "https://pbs.twimg.com/profile_images/1/a.jpg"
+                .to_string(),
+            register_time: None,
+            region: String::new(),
+            source: String::new(),
+            changed_name_count: 0,
+            first_seen_run: None,
+            last_seen_run: None,
+            page_url: "https://example.com/".to_string(),
+            removed_at: None,
+            id_created_at: None,
+            last_seen_at: None,
+        }
+    }
+}
+
+/// `注册：` of a card as a UTC date, naive times are in `X_SITE_TZ`
+pub fn register_date(raw: &str) -> Result<Date, String> {
+    let site_tz =
+        ENV_SETTINGS.x_site_tz.unwrap_or(chrono_tz::UTC);
+    parse_date(raw, site_tz).ok_or_else(|| {
+        "not a known date format".to_string()
+    })
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -24,72 +140,592 @@ pub enum Relation {}
 
 impl ActiveModelBehavior for ActiveModel {}
 
-pub async fn save_to_db(db: &DatabaseConnection, models: Vec<Model>) -> anyhow::Result<()> {
-    if models.is_empty() {
-        return Ok(());
-    }
-    let active_models: Vec<ActiveModel> = models.into_iter().map(Into::into).collect();
-
-    Entity::insert_many(active_models)
-        .on_conflict(
-            sea_orm::sea_query::OnConflict::column(Column::UserId)
-                .update_columns([
-                    Column::Name,
-                    Column::Handle,
-                    Column::ProfileUrl,
-                    Column::Avatar,
-                    Column::RegisterTime,
-                    Column::ChangedNameCount,
-                ])
-                .to_owned(),
+/// bind parameters one statement may carry on `backend`
+pub fn max_bind_params(backend: DbBackend) -> usize {
+    match backend {
+        // SQLITE_MAX_VARIABLE_NUMBER since 3.32
+        DbBackend::Sqlite => 32_766,
+        _ => 65_535,
+    }
+}
+
+/// rows of `columns` values each that fit in one statement
+pub fn rows_per_statement(
+    backend: DbBackend,
+    columns: usize,
+) -> usize {
+    (max_bind_params(backend) / columns.max(1)).max(1)
+}
+
+/// columns an upsert overwrites, the others keep their first value
+pub const UPSERT_COLUMNS: [Column; 13] = [
+    Column::Name,
+    Column::Handle,
+    Column::ProfileUrl,
+    Column::Avatar,
+    Column::RegisterTime,
+    Column::Region,
+    Column::Source,
+    Column::ChangedNameCount,
+    Column::LastSeenRun,
+    Column::LastSeenAt,
+    Column::PageUrl,
+    // seen again, so reinstate it
+    Column::RemovedAt,
+    // rows older than the column get it here
+    Column::IdCreatedAt,
+];
+
+/// columns a re-parse overwrites, the lineage and the tombstone stay as
+/// the later runs left them
+pub const REPARSE_COLUMNS: [Column; 9] = [
+    Column::Name,
+    Column::Handle,
+    Column::ProfileUrl,
+    Column::Avatar,
+    Column::RegisterTime,
+    Column::Region,
+    Column::Source,
+    Column::ChangedNameCount,
+    Column::IdCreatedAt,
+];
+
+/// `ON CONFLICT` of every write to astroturfers_x
+pub fn on_conflict() -> OnConflict {
+    OnConflict::column(Column::UserId)
+        .update_columns(UPSERT_COLUMNS)
+        .to_owned()
+}
+
+/// `ON CONFLICT` of a re-parse of archived pages
+pub fn reparse_on_conflict() -> OnConflict {
+    OnConflict::column(Column::UserId)
+        .update_columns(REPARSE_COLUMNS)
+        .to_owned()
+}
+
+/// every column in the order `fields` gives them, the layout of the
+/// exports and of the COPY of `bulk`
+pub const COLUMNS: [Column; 15] = [
+    Column::UserId,
+    Column::Name,
+    Column::Handle,
+    Column::ProfileUrl,
+    Column::Avatar,
+    Column::RegisterTime,
+    Column::Region,
+    Column::Source,
+    Column::ChangedNameCount,
+    Column::FirstSeenRun,
+    Column::LastSeenRun,
+    Column::PageUrl,
+    Column::RemovedAt,
+    Column::IdCreatedAt,
+    Column::LastSeenAt,
+];
+
+/// `model` as text in the order of `COLUMNS`, `None` for NULL
+pub fn fields(model: &Model) -> [Option<String>; 15] {
+    let Model {
+        user_id,
+        name,
+        handle,
+        profile_url,
+        avatar,
+        register_time,
+        region,
+        source,
+        changed_name_count,
+        first_seen_run,
+        last_seen_run,
+        page_url,
+        removed_at,
+        id_created_at,
+        last_seen_at,
+    } = model;
+    [
+        Some(user_id.clone()),
+        Some(name.clone()),
+        Some(handle.clone()),
+        Some(profile_url.clone()),
+        Some(avatar.clone()),
+        register_time.map(|date| date.to_string()),
+        Some(region.clone()),
+        Some(source.clone()),
+        Some(changed_name_count.to_string()),
+        first_seen_run.map(|run| run.to_string()),
+        last_seen_run.map(|run| run.to_string()),
+        Some(page_url.clone()),
+        removed_at.map(|at| at.to_rfc3339()),
+        id_created_at.map(|at| at.to_rfc3339()),
+        last_seen_at.map(|at| at.to_rfc3339()),
+    ]
+}
+
+/// `fields` as a line of CSV, quoted so that only `None` reads as NULL
+pub fn csv_line(
+    out: &mut String,
+    fields: impl IntoIterator<Item = Option<String>>,
+) {
+    for (i, field) in fields.into_iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        if let Some(field) = field {
+            out.push('"');
+            out.push_str(&field.replace('"', "\"\""));
+            out.push('"');
+        }
+    }
+    out.push('\n');
+}
+
+/// `models` that pass `validate`, and the others with their problems
+pub fn split_invalid(
+    models: Vec<Model>,
+) -> (Vec<Model>, Vec<(Model, Vec<String>)>) {
+    let mut rejected = Vec::new();
+    let models = models
+        .into_iter()
+        .filter_map(|model| {
+            let problems = validate::problems(&model);
+            if problems.is_empty() {
+                return Some(model);
+            }
+            log::warn!(
+                "quarantine {:?} of {}: {}",
+                model.user_id,
+                model.page_url,
+                problems.join("; ")
+            );
+            rejected.push((model, problems));
+            None
+        })
+        .collect();
+    (models, rejected)
+}
+
+/// `models` as seen by run `run_id` at `seen_at`, ready to upsert
+pub fn stamp(
+    run_id: i64,
+    seen_at: DateTimeUtc,
+    models: Vec<Model>,
+) -> Vec<Model> {
+    models
+        .into_iter()
+        .map(|model| Model {
+            first_seen_run: Some(run_id),
+            last_seen_run: Some(run_id),
+            last_seen_at: Some(seen_at),
+            removed_at: None,
+            id_created_at: parse_snowflake_time(
+                &model.user_id,
+            ),
+            ..model
+        })
+        .collect()
+}
+
+/// The last row of every key, in first seen order. One statement
+/// must not upsert a row twice.
+pub fn last_per_key<T, K>(
+    rows: Vec<T>,
+    key: impl Fn(&T) -> K,
+) -> Vec<T>
+where
+    K: Eq + Hash,
+{
+    let mut index = HashMap::new();
+    let mut last: Vec<T> = Vec::with_capacity(rows.len());
+    for row in rows {
+        match index.entry(key(&row)) {
+            Entry::Occupied(at) => last[*at.get()] = row,
+            Entry::Vacant(at) => {
+                at.insert(last.len());
+                last.push(row);
+            }
+        }
+    }
+    last
+}
+
+/// Upsert stamped `models` in statements under `max_bind_params`,
+/// returns how many rows were upserted. `bulk::BulkWriter` puts it
+/// in a transaction with the history and quarantine rows.
+pub async fn upsert<C: ConnectionTrait>(
+    db: &C,
+    models: Vec<Model>,
+) -> Result<u32, DbErr> {
+    upsert_last(db, models, on_conflict()).await
+}
+
+/// `upsert` of rows re-parsed from old snapshots, only the columns
+/// of `REPARSE_COLUMNS` change in stored rows
+pub async fn upsert_reparsed<C: ConnectionTrait>(
+    db: &C,
+    models: Vec<Model>,
+) -> Result<u32, DbErr> {
+    upsert_last(db, models, reparse_on_conflict()).await
+}
+
+async fn upsert_last<C: ConnectionTrait>(
+    db: &C,
+    models: Vec<Model>,
+    on_conflict: OnConflict,
+) -> Result<u32, DbErr> {
+    let models =
+        last_per_key(models, |m| m.user_id.clone());
+    let count = models.len() as u32;
+    upsert_with(db, models, on_conflict).await?;
+    Ok(count)
+}
+
+/// Insert `models`, one per `user_id`, in statements under
+/// `max_bind_params` with `on_conflict`. Returns how many rows were
+/// inserted or updated, `import` picks its own `ON CONFLICT`.
+pub async fn upsert_with<C: ConnectionTrait>(
+    db: &C,
+    models: Vec<Model>,
+    on_conflict: OnConflict,
+) -> Result<u64, DbErr> {
+    let per_statement = rows_per_statement(
+        db.get_database_backend(),
+        Column::iter().count(),
+    );
+    let mut written = 0;
+    let mut rows = models
+        .into_iter()
+        .map(ActiveModel::from)
+        .peekable();
+    while rows.peek().is_some() {
+        let chunk: Vec<ActiveModel> =
+            rows.by_ref().take(per_statement).collect();
+        written += Entity::insert_many(chunk)
+            .on_conflict(on_conflict.clone())
+            .exec_without_returning(db)
+            .await?;
+    }
+    Ok(written)
+}
+
+/// marks the live rows as seen by run `run_id` now
+fn touch(run_id: i64) -> UpdateMany<Entity> {
+    Entity::update_many()
+        .col_expr(
+            Column::LastSeenRun,
+            Expr::value(Some(run_id)),
+        )
+        .col_expr(
+            Column::LastSeenAt,
+            Expr::value(Some(chrono::Utc::now())),
+        )
+        .filter(Column::RemovedAt.is_null())
+}
+
+/// marks the live rows of `page_url` as seen by run `run_id`
+pub fn touch_query(
+    run_id: i64,
+    page_url: &str,
+) -> UpdateMany<Entity> {
+    touch(run_id).filter(Column::PageUrl.eq(page_url))
+}
+
+/// Marks the live rows of `user_ids` as seen by run `run_id`. A
+/// quarantined row is still listed, its stored account must not be
+/// tombstoned.
+pub fn touch_accounts_query<'m>(
+    run_id: i64,
+    user_ids: impl IntoIterator<Item = &'m str>,
+) -> UpdateMany<Entity> {
+    touch(run_id).filter(Column::UserId.is_in(user_ids))
+}
+
+/// Mark the live rows of an unchanged page as seen by run `run_id`,
+/// returns how many.
+pub async fn touch_page<C: ConnectionTrait>(
+    db: &C,
+    run_id: i64,
+    page_url: &str,
+) -> Result<u64, DbErr> {
+    let res =
+        touch_query(run_id, page_url).exec(db).await?;
+    Ok(res.rows_affected)
+}
+
+/// Tombstone the live accounts `run` did not see, returns how many.
+///
+/// Incomplete runs tombstone nothing, and a run that would remove more
+/// than `MAX_TOMBSTONE_SHARE` of the live accounts is refused.
+pub async fn tombstone_unseen(
+    db: &DatabaseConnection,
+    run: &crawl_run::Model,
+) -> anyhow::Result<u64> {
+    if !run.is_complete() {
+        log::warn!(
+            "run {} is incomplete, tombstone nothing",
+            run.id
+        );
+        return Ok(0);
+    }
+    let live = Column::RemovedAt.is_null();
+    let unseen = Condition::all().add(live.clone()).add(
+        Condition::any()
+            .add(Column::LastSeenRun.is_null())
+            .add(Column::LastSeenRun.ne(run.id)),
+    );
+    let live_count =
+        Entity::find().filter(live).count(db).await?;
+    let unseen_count = Entity::find()
+        .filter(unseen.clone())
+        .count(db)
+        .await?;
+    let max_count = live_count as f64 * MAX_TOMBSTONE_SHARE;
+    if unseen_count as f64 > max_count {
+        anyhow::bail!(
+            "refuse to tombstone {} of {} accounts after run {}",
+            unseen_count,
+            live_count,
+            run.id
+        );
+    }
+    let res = Entity::update_many()
+        .col_expr(
+            Column::RemovedAt,
+            Expr::value(Some(chrono::Utc::now())),
         )
+        .filter(unseen)
         .exec(db)
         .await?;
-    Ok(())
+    Ok(res.rows_affected)
+}
+
+/// Rows seen by run `run_id` whose `register_time` disagrees with
+/// their `id_created_at`. The rows are streamed, older ones were
+/// checked by the runs that wrote them.
+pub async fn register_mismatches(
+    db: &DatabaseConnection,
+    run_id: i64,
+) -> Result<Vec<Model>, DbErr> {
+    let mut rows = std::pin::pin!(
+        Entity::find()
+            .filter(Column::LastSeenRun.eq(run_id))
+            .filter(Column::RegisterTime.is_not_null())
+            .filter(Column::IdCreatedAt.is_not_null())
+            .stream(db)
+            .await?
+    );
+    let mut mismatches = Vec::new();
+    while let Some(row) = rows.try_next().await? {
+        if row.register_mismatch() {
+            mismatches.push(row);
+        }
+    }
+    Ok(mismatches)
 }
 
 #[cfg(test)]
 mod tests {
+    use super::super::crawl_run::Tally;
+    use super::super::db::test_db::TestDb;
+    use super::super::health::RunHealth;
     use super::*;
-    use sea_orm::{ConnectionTrait, Database, Schema};
+
+    #[test]
+    fn columns_cover_the_table() {
+        let mut columns: Vec<&str> =
+            Column::iter().map(|c| c.as_str()).collect();
+        let mut ordered: Vec<&str> =
+            COLUMNS.iter().map(|c| c.as_str()).collect();
+        columns.sort();
+        ordered.sort();
+        assert_eq!(columns, ordered);
+    }
+
+    #[test]
+    fn register_mismatch() {
+        let created =
+            parse_snowflake_time("1830540823630675969");
+        let model = |register_time| Model {
+            register_time,
+            id_created_at: created,
+            ..Model::test_account("1830540823630675969")
+        };
+        let date =
+            |d| chrono::NaiveDate::from_ymd_opt(2024, 9, d);
+        assert!(!model(date(2)).register_mismatch());
+        assert!(!model(date(3)).register_mismatch());
+        assert!(model(date(9)).register_mismatch());
+        assert!(!model(None).register_mismatch());
+    }
+
+    #[test]
+    fn statements_fit_the_bind_limit() {
+        let columns = Column::iter().count();
+        for backend in
+            [DbBackend::Postgres, DbBackend::Sqlite]
+        {
+            let max = max_bind_params(backend);
+            let rows = rows_per_statement(backend, columns);
+            assert!(rows * columns <= max);
+            assert!((rows + 1) * columns > max);
+            assert_eq!(rows_per_statement(backend, 0), max);
+        }
+    }
+
+    #[test]
+    fn last_row_per_key() {
+        let rows =
+            vec![("a", 1), ("b", 2), ("a", 3), ("c", 4)];
+        assert_eq!(
+            last_per_key(rows, |row| row.0),
+            vec![("a", 3), ("b", 2), ("c", 4)]
+        );
+    }
+
+    /// a finished run that wrote `records_written` rows of one page
+    async fn finished_run(
+        db: &DatabaseConnection,
+        records_written: u32,
+    ) -> anyhow::Result<crawl_run::Model> {
+        let run = crawl_run::start(db).await?;
+        let tally = Tally {
+            pages_attempted: 1,
+            pages_succeeded: 1,
+            pages_failed: 0,
+            records_written,
+        };
+        let health = RunHealth::default();
+        Ok(crawl_run::finish(
+            db, run.id, tally, &health, None,
+        )
+        .await?)
+    }
+
+    /// upsert `user_ids` as seen by `run_id`
+    async fn see(
+        db: &DatabaseConnection,
+        run_id: i64,
+        user_ids: &[&str],
+    ) -> anyhow::Result<()> {
+        let models = user_ids
+            .iter()
+            .map(|user_id| Model::test_account(user_id))
+            .collect();
+        upsert(
+            db,
+            stamp(run_id, chrono::Utc::now(), models),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn removed(
+        db: &DatabaseConnection,
+    ) -> anyhow::Result<Vec<String>> {
+        Ok(Entity::find()
+            .filter(Column::RemovedAt.is_not_null())
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|row| row.user_id)
+            .collect())
+    }
 
     #[tokio::test]
-    async fn test_create_table() -> anyhow::Result<()> {
-        dotenvy::dotenv().ok();
-        let database_url = std::env::var("PG_DB")
-            .expect("PG_DB must be set");
-        // 建立到数据库的连接
-        let db = Database::connect(&database_url).await?;
-
-        // 获取数据库后端类型（PostgreSQL、MySQL等）
-        let builder = db.get_database_backend();
-        // 创建数据库模式对象
-        let schema = Schema::new(builder);
-        // 根据实体定义创建表的操作对象
-        let mut create_table_op =
-            schema.create_table_from_entity(Entity);
-        // 设置如果表不存在则创建的选项
-        create_table_op.if_not_exists();
-
-        // 执行创建表的操作
-        let res = db.execute(&create_table_op).await;
-        // 断言操作成功，如果失败则打印错误信息
-        assert!(
-            res.is_ok(),
-            "Failed to create table: {:?}",
-            res.err()
+    async fn tombstone_complete_runs_only()
+    -> anyhow::Result<()> {
+        let test = TestDb::new().await?;
+        let db = &test.db;
+        let first = crawl_run::start(db).await?;
+        see(db, first.id, &["1", "2", "3", "4"]).await?;
+
+        // still running, so nothing is known to be gone
+        let incomplete = crawl_run::start(db).await?;
+        see(db, incomplete.id, &["1"]).await?;
+        assert_eq!(
+            tombstone_unseen(db, &incomplete).await?,
+            0
         );
+        assert!(removed(db).await?.is_empty());
+
+        let run = finished_run(db, 3).await?;
+        see(db, run.id, &["1", "2", "3"]).await?;
+        assert_eq!(tombstone_unseen(db, &run).await?, 1);
+        assert_eq!(removed(db).await?, ["4"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn refuse_to_tombstone_most_accounts()
+    -> anyhow::Result<()> {
+        let test = TestDb::new().await?;
+        let db = &test.db;
+        let first = crawl_run::start(db).await?;
+        see(db, first.id, &["1", "2", "3", "4"]).await?;
+
+        // 3 of 4 unseen is more than MAX_TOMBSTONE_SHARE
+        let run = finished_run(db, 1).await?;
+        see(db, run.id, &["1"]).await?;
+        assert!(tombstone_unseen(db, &run).await.is_err());
+        assert!(removed(db).await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reinstate_an_account_seen_again()
+    -> anyhow::Result<()> {
+        let test = TestDb::new().await?;
+        let db = &test.db;
+        let first = crawl_run::start(db).await?;
+        see(db, first.id, &["1", "2", "3"]).await?;
+        let run = finished_run(db, 2).await?;
+        see(db, run.id, &["1", "2"]).await?;
+        assert_eq!(tombstone_unseen(db, &run).await?, 1);
+        assert_eq!(removed(db).await?, ["3"]);
 
-        // 验证表是否存在，通过查询表中的记录数来确认
-        let count = Entity::find().count(&db).await;
-        // 断言查询成功，如果失败则说明表可能不存在
+        let run = finished_run(db, 3).await?;
+        see(db, run.id, &["1", "2", "3"]).await?;
+        assert!(removed(db).await?.is_empty());
+        assert_eq!(tombstone_unseen(db, &run).await?, 0);
+        let back = Entity::find_by_id("3")
+            .one(db)
+            .await?
+            .expect("account 3");
+        assert_eq!(back.last_seen_run, Some(run.id));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn register_mismatches_of_one_run()
+    -> anyhow::Result<()> {
+        let test = TestDb::new().await?;
+        let db = &test.db;
+        let user_id = "1830540823630675969";
+        let minted = Model {
+            register_time: chrono::NaiveDate::from_ymd_opt(
+                2020, 1, 1,
+            ),
+            ..Model::test_account(user_id)
+        };
+        let first = crawl_run::start(db).await?;
+        let now = chrono::Utc::now();
+        upsert(
+            db,
+            stamp(first.id, now, vec![minted.clone()]),
+        )
+        .await?;
+        let run = crawl_run::start(db).await?;
         assert!(
-            count.is_ok(),
-            "Failed to count rows, table might not exist: {:?}",
-            count.err()
+            register_mismatches(db, run.id)
+                .await?
+                .is_empty()
         );
 
-        // 返回成功的Result
+        upsert(db, stamp(run.id, now, vec![minted]))
+            .await?;
+        let rows = register_mismatches(db, run.id).await?;
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].user_id, user_id);
         Ok(())
     }
 }
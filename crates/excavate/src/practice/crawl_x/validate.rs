@@ -0,0 +1,95 @@
+//! sanity checks of a parsed row before it reaches astroturfers_x
+use super::to_db::Model;
+use reqwest::Url;
+
+/// longest handle X allows
+const MAX_HANDLE_LEN: usize = 15;
+
+/// what is wrong with `model`, empty when it may be saved
+pub fn problems(model: &Model) -> Vec<String> {
+    let mut problems = Vec::new();
+    if !is_snowflake(&model.user_id) {
+        problems.push(format!(
+            "user_id {:?} is not numeric",
+            model.user_id
+        ));
+    }
+    if !is_handle(&model.handle) {
+        problems.push(format!(
+            "handle {:?} is not an X handle",
+            model.handle
+        ));
+    }
+    for (field, url) in [
+        ("profile_url", &model.profile_url),
+        ("avatar", &model.avatar),
+    ] {
+        if !is_web_url(url) {
+            problems.push(format!(
+                "{} {:?} is not an http(s) url",
+                field, url
+            ));
+        }
+    }
+    problems
+}
+
+fn is_snowflake(user_id: &str) -> bool {
+    !user_id.is_empty()
+        && user_id.bytes().all(|b| b.is_ascii_digit())
+        && user_id.parse::<u64>().is_ok()
+}
+
+/// 1 to 15 lowercase letters, digits or underscores, the form
+/// `x_identity::handle` gives
+fn is_handle(handle: &str) -> bool {
+    (1..=MAX_HANDLE_LEN).contains(&handle.len())
+        && handle.bytes().all(|b| {
+            b.is_ascii_lowercase()
+                || b.is_ascii_digit()
+                || b == b'_'
+        })
+}
+
+fn is_web_url(url: &str) -> bool {
+    Url::parse(url).is_ok_and(|url| {
+        matches!(url.scheme(), "http" | "https")
+            && url.host_str().is_some()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model() -> Model {
+        Model::test_account("1830540823630675969")
+    }
+
+    #[test]
+    fn valid_model() {
+        assert!(problems(&model()).is_empty());
+    }
+
+    #[test]
+    fn invalid_fields() {
+        let bad = Model {
+            user_id: "ID: 18305".to_string(),
+            handle: "@has space".to_string(),
+            profile_url: "/ynhu434128".to_string(),
+            avatar: "javascript:alert(1)".to_string(),
+            ..model()
+        };
+        let problems = problems(&bad);
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+        assert!(problems[0].starts_with("user_id"));
+
+        for handle in
+            ["@ynhu", "YnHu", "", "a_very_long_handle_x"]
+        {
+            assert!(!is_handle(handle), "{}", handle);
+        }
+        assert!(is_handle("a_1"));
+        assert!(!is_snowflake("99999999999999999999"));
+    }
+}
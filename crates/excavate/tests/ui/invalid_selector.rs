@@ -0,0 +1,10 @@
+use excavate::extract::Extract;
+
+#[derive(Extract)]
+#[extract(container = "li.item")]
+struct Item {
+    #[extract(selector = "h3[", trim)]
+    title: String,
+}
+
+fn main() {}
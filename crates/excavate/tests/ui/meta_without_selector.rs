@@ -0,0 +1,10 @@
+use excavate::extract::Extract;
+
+#[derive(Extract)]
+#[extract(container = "li.item")]
+struct Item {
+    #[extract(meta = "城市")]
+    city: String,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use excavate::extract::Extract;
+
+#[derive(Extract)]
+struct Item {
+    #[extract(selector = "h3")]
+    title: String,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use excavate::extract::Extract;
+
+#[derive(Extract)]
+#[extract(container = "li.item")]
+struct Item {
+    #[extract(selector = "a", text, attr = "href")]
+    link: String,
+}
+
+fn main() {}
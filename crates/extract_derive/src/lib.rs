@@ -0,0 +1,389 @@
+//! `#[derive(Extract)]`, typed html extraction for `excavate::extract`
+//!
+//! ```ignore
+//! #[derive(Extract)]
+//! #[extract(
+//!     container = "article.user-card",
+//!     meta(selector = "div.user-meta span", separator = "：")
+//! )]
+//! struct Card {
+//!     #[extract(selector = "h2.user-name", text, trim)]
+//!     name: String,
+//!     #[extract(selector = "img.user-avatar", attr = "src")]
+//!     avatar: String,
+//!     #[extract(meta = "改名次数", default)]
+//!     changed_name_count: u32,
+//!     #[extract(skip)]
+//!     page_url: String,
+//! }
+//! ```
+//!
+//! Every selector is parsed while compiling. A required field that is
+//! missing or empty, or a value that does not parse into the field
+//! type, is an `ExtractError` at runtime. A selector reads the text,
+//! which `text` spells out, or the attribute named by `attr`.
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    Data, DeriveInput, Fields, GenericArgument, Ident,
+    LitStr, PathArguments, Type, parse_macro_input,
+};
+
+#[proc_macro_derive(Extract, attributes(extract))]
+pub fn derive_extract(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[derive(Default)]
+struct StructAttrs {
+    container: Option<LitStr>,
+    meta_selector: Option<LitStr>,
+    meta_separator: Option<LitStr>,
+}
+
+enum Source {
+    Selector(LitStr),
+    Meta(LitStr),
+}
+
+#[derive(Default)]
+struct FieldAttrs {
+    source: Option<Source>,
+    attr: Option<LitStr>,
+    text: bool,
+    trim: bool,
+    strip_prefix: Option<LitStr>,
+    default: bool,
+    skip: bool,
+    with: Option<syn::Path>,
+}
+
+fn check_selector(lit: &LitStr) -> syn::Result<()> {
+    scraper::Selector::parse(&lit.value())
+        .map(|_| ())
+        .map_err(|e| {
+            syn::Error::new(
+                lit.span(),
+                format!("invalid selector: {e}"),
+            )
+        })
+}
+
+fn struct_attrs(
+    input: &DeriveInput,
+) -> syn::Result<StructAttrs> {
+    let mut attrs = StructAttrs::default();
+    for attr in &input.attrs {
+        if !attr.path().is_ident("extract") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("container") {
+                attrs.container = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("meta") {
+                meta.parse_nested_meta(|inner| {
+                    if inner.path.is_ident("selector") {
+                        attrs.meta_selector = Some(inner.value()?.parse()?);
+                    } else if inner.path.is_ident("separator") {
+                        attrs.meta_separator =
+                            Some(inner.value()?.parse()?);
+                    } else {
+                        return Err(inner.error("expected `selector` or `separator`"));
+                    }
+                    Ok(())
+                })?;
+            } else {
+                return Err(meta.error("expected `container` or `meta(..)`"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(attrs)
+}
+
+fn field_attrs(
+    field: &syn::Field,
+) -> syn::Result<FieldAttrs> {
+    let mut attrs = FieldAttrs::default();
+    for attr in &field.attrs {
+        if !attr.path().is_ident("extract") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            let path = &meta.path;
+            if path.is_ident("selector") {
+                let lit: LitStr = meta.value()?.parse()?;
+                check_selector(&lit)?;
+                attrs.source = Some(Source::Selector(lit));
+            } else if path.is_ident("meta") {
+                attrs.source = Some(Source::Meta(
+                    meta.value()?.parse()?,
+                ));
+            } else if path.is_ident("attr") {
+                attrs.attr = Some(meta.value()?.parse()?);
+            } else if path.is_ident("text") {
+                attrs.text = true;
+            } else if path.is_ident("trim") {
+                attrs.trim = true;
+            } else if path.is_ident("strip_prefix") {
+                attrs.strip_prefix =
+                    Some(meta.value()?.parse()?);
+            } else if path.is_ident("default") {
+                attrs.default = true;
+            } else if path.is_ident("skip") {
+                attrs.skip = true;
+            } else if path.is_ident("with") {
+                let lit: LitStr = meta.value()?.parse()?;
+                attrs.with = Some(lit.parse()?);
+            } else {
+                return Err(
+                    meta.error("unknown extract attribute")
+                );
+            }
+            Ok(())
+        })?;
+    }
+    Ok(attrs)
+}
+
+/// `T` of an `Option<T>`
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) =
+        &segment.arguments
+    else {
+        return None;
+    };
+    match args.args.first()? {
+        GenericArgument::Type(inner) => Some(inner),
+        _ => None,
+    }
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let rt = quote!(::excavate::extract::__private);
+    let name = &input.ident;
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            &input,
+            "Extract can only be derived for structs",
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(syn::Error::new_spanned(
+            &input,
+            "Extract needs named fields",
+        ));
+    };
+    let attrs = struct_attrs(&input)?;
+    let Some(container) = &attrs.container else {
+        return Err(syn::Error::new_spanned(
+            name,
+            "missing #[extract(container = \"..\")]",
+        ));
+    };
+    check_selector(container)?;
+
+    // every selector is parsed once, on first use
+    let mut selectors = vec![container.clone()];
+    let separator = attrs
+        .meta_separator
+        .as_ref()
+        .map_or_else(|| "：".to_string(), LitStr::value);
+    let meta_items = match &attrs.meta_selector {
+        Some(selector) => {
+            check_selector(selector)?;
+            let index = selectors.len();
+            selectors.push(selector.clone());
+            quote! {
+                let __meta = #rt::meta_items(
+                    element,
+                    &__SELECTORS[#index],
+                    #separator,
+                );
+            }
+        }
+        None => quote!(),
+    };
+
+    let mut inits = Vec::new();
+    let mut labels = Vec::new();
+    for field in &fields.named {
+        let ident =
+            field.ident.as_ref().expect("named field");
+        let attrs = field_attrs(field)?;
+        if let Some(Source::Meta(label)) = &attrs.source {
+            labels.push(label.clone());
+        }
+        if attrs.skip {
+            inits.push(quote!(#ident: ::std::default::Default::default()));
+            continue;
+        }
+        inits.push(field_init(
+            &rt,
+            ident,
+            &field.ty,
+            &attrs,
+            &mut selectors,
+            meta_items.is_empty(),
+        )?);
+    }
+
+    let unknown_meta = match &attrs.meta_selector {
+        Some(selector) => quote! {
+            fn unknown_meta(
+                element: #rt::ElementRef<'_>,
+            ) -> ::std::vec::Vec<::std::string::String> {
+                static __META: #rt::LazyLock<#rt::Selector> =
+                    #rt::LazyLock::new(|| {
+                        #rt::Selector::parse(#selector)
+                            .expect("checked by #[derive(Extract)]")
+                    });
+                #rt::unknown_meta(
+                    element,
+                    &__META,
+                    #separator,
+                    &[#(#labels),*],
+                )
+            }
+        },
+        None => quote!(),
+    };
+
+    let count = selectors.len();
+    Ok(quote! {
+        impl ::excavate::extract::Extract for #name {
+            const CONTAINER: &'static str = #container;
+
+            fn extract(
+                element: #rt::ElementRef<'_>,
+            ) -> ::std::result::Result<Self, ::excavate::extract::ExtractError> {
+                static __SELECTORS: #rt::LazyLock<[#rt::Selector; #count]> =
+                    #rt::LazyLock::new(|| {
+                        [#(#rt::Selector::parse(#selectors)
+                            .expect("checked by #[derive(Extract)]")),*]
+                    });
+                #meta_items
+                ::std::result::Result::Ok(Self {
+                    #(#inits,)*
+                })
+            }
+
+            #unknown_meta
+        }
+    })
+}
+
+fn field_init(
+    rt: &TokenStream2,
+    ident: &Ident,
+    ty: &Type,
+    attrs: &FieldAttrs,
+    selectors: &mut Vec<LitStr>,
+    no_meta: bool,
+) -> syn::Result<TokenStream2> {
+    let field = ident.to_string();
+    let (raw, source) = match &attrs.source {
+        Some(Source::Selector(selector)) => {
+            let index = selectors.len();
+            selectors.push(selector.clone());
+            let raw = match &attrs.attr {
+                Some(_) if attrs.text => {
+                    return Err(syn::Error::new_spanned(
+                        ident,
+                        "`text` and `attr` exclude each other",
+                    ));
+                }
+                Some(attr) => quote! {
+                    #rt::select_attr(element, &__SELECTORS[#index], #attr)
+                },
+                None => quote! {
+                    #rt::select_text(element, &__SELECTORS[#index])
+                },
+            };
+            (raw, selector.value())
+        }
+        Some(Source::Meta(label)) => {
+            if no_meta {
+                return Err(syn::Error::new_spanned(
+                    label,
+                    "needs #[extract(meta(selector = \"..\"))] on the struct",
+                ));
+            }
+            (
+                quote!(#rt::meta_value(&__meta, #label)),
+                label.value(),
+            )
+        }
+        None => {
+            return Err(syn::Error::new_spanned(
+                ident,
+                "expected `selector`, `meta` or `skip`",
+            ));
+        }
+    };
+
+    let mut value = raw;
+    if attrs.trim {
+        value =
+            quote!(#value.map(|v| v.trim().to_string()));
+    }
+    if let Some(prefix) = &attrs.strip_prefix {
+        value = quote!(#value.map(|v| #rt::strip_prefix(v, #prefix)));
+    }
+    // an empty value is as missing as no match at all
+    value = quote!(#value.filter(|v| !v.is_empty()));
+
+    let inner = option_inner(ty);
+    let target = inner.unwrap_or(ty);
+    let convert = match &attrs.with {
+        Some(with) => {
+            quote!(#rt::convert::<#target, _>(#field, v, #with))
+        }
+        None => quote!(#rt::parse::<#target>(#field, v)),
+    };
+    let init = if inner.is_some() {
+        quote! {
+            match #value {
+                ::std::option::Option::Some(v) => {
+                    ::std::option::Option::Some(#convert?)
+                }
+                ::std::option::Option::None => ::std::option::Option::None,
+            }
+        }
+    } else if attrs.default {
+        quote! {
+            match #value {
+                ::std::option::Option::Some(v) => #convert?,
+                ::std::option::Option::None => {
+                    ::std::default::Default::default()
+                }
+            }
+        }
+    } else {
+        quote! {
+            match #value {
+                ::std::option::Option::Some(v) => #convert?,
+                ::std::option::Option::None => {
+                    return ::std::result::Result::Err(
+                        ::excavate::extract::ExtractError::Missing {
+                            field: #field,
+                            source: #source,
+                        },
+                    );
+                }
+            }
+        }
+    };
+    Ok(quote!(#ident: #init))
+}
@@ -0,0 +1,182 @@
+//! schema migrations of the crawl_x tables
+//!
+//! Every table lives in the schema of the profile, `DB_SCHEMA` or the
+//! profile name (see `utils::config::profile`). The connections of
+//! `connect` search only that schema, so neither the migrations nor the
+//! entities name one.
+use sea_orm::{
+    ConnectOptions, Database, DatabaseConnection, DbBackend,
+};
+use sea_orm_migration::prelude::*;
+use utils::config::env::ENV_SETTINGS;
+
+mod m20261018_000001_create_astroturfers_x;
+mod m20261018_000002_add_region_source;
+mod m20261018_000003_register_time_date;
+mod m20261018_000004_create_history;
+mod m20261018_000005_crawl_runs;
+mod m20261018_000006_removed_at;
+mod m20261018_000007_page_snapshots;
+mod m20261018_000008_http_cache;
+mod m20261018_000009_page_health;
+mod m20261018_000010_quarantine;
+mod m20261018_000011_id_created_at;
+mod m20261018_000012_normalize_identities;
+mod m20261018_000013_last_seen_at;
+
+/// schema of every crawl_x table
+pub fn schema() -> &'static str {
+    ENV_SETTINGS.db_schema()
+}
+
+/// name of the latest migration, the version of the schema it leaves
+pub fn version() -> String {
+    Migrator::migrations()
+        .last()
+        .map(|migration| migration.name().to_string())
+        .unwrap_or_default()
+}
+
+/// Apply the pending migrations to `db`, the crawlers call it on
+/// connect so they never need `sea-orm-migration` themselves.
+pub async fn up(
+    db: &DatabaseConnection,
+) -> Result<(), DbErr> {
+    Migrator::up(db, None).await
+}
+
+/// Connect to Postgres `url` searching only `schema()`, which is
+/// created when missing. A `sqlite:` url has no schema to search.
+pub async fn connect(
+    url: &str,
+) -> Result<DatabaseConnection, DbErr> {
+    connect_in(url, schema()).await
+}
+
+/// `connect` to another schema than the profile one, as the tests do
+pub async fn connect_in(
+    url: &str,
+    schema: &str,
+) -> Result<DatabaseConnection, DbErr> {
+    let mut options = ConnectOptions::new(url);
+    options.set_schema_search_path(schema);
+    let db = Database::connect(options).await?;
+    // SQLite has no schemas, nor a migration table to adopt
+    if db.get_database_backend() == DbBackend::Sqlite {
+        return Ok(db);
+    }
+    db.execute_unprepared(&format!(
+        "CREATE SCHEMA IF NOT EXISTS \"{}\"",
+        schema
+    ))
+    .await?;
+    db.execute_unprepared(&adopt_migration_table(schema))
+        .await?;
+    Ok(db)
+}
+
+/// Move `seaql_migrations` of a setup from before `DB_SCHEMA` out of
+/// `public`, the migrator looks for it in the searched schema now.
+fn adopt_migration_table(schema: &str) -> String {
+    format!(
+        r#"DO $$
+BEGIN
+    IF to_regclass('"{0}".seaql_migrations') IS NULL
+        AND to_regclass('"{0}".astroturfers_x') IS NOT NULL
+        AND to_regclass('public.seaql_migrations') IS NOT NULL
+    THEN
+        ALTER TABLE public.seaql_migrations SET SCHEMA "{0}";
+    END IF;
+END $$"#,
+        schema
+    )
+}
+
+pub struct Migrator;
+
+#[async_trait::async_trait]
+impl MigratorTrait for Migrator {
+    fn migrations() -> Vec<Box<dyn MigrationTrait>> {
+        vec![
+            Box::new(m20261018_000001_create_astroturfers_x::Migration),
+            Box::new(m20261018_000002_add_region_source::Migration),
+            Box::new(
+                m20261018_000003_register_time_date::Migration,
+            ),
+            Box::new(m20261018_000004_create_history::Migration),
+            Box::new(m20261018_000005_crawl_runs::Migration),
+            Box::new(m20261018_000006_removed_at::Migration),
+            Box::new(m20261018_000007_page_snapshots::Migration),
+            Box::new(m20261018_000008_http_cache::Migration),
+            Box::new(m20261018_000009_page_health::Migration),
+            Box::new(m20261018_000010_quarantine::Migration),
+            Box::new(m20261018_000011_id_created_at::Migration),
+            Box::new(
+                m20261018_000012_normalize_identities::Migration,
+            ),
+            Box::new(m20261018_000013_last_seen_at::Migration),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn both_ways(
+        db: &DatabaseConnection,
+    ) -> Result<(), DbErr> {
+        Migrator::up(db, None).await?;
+        Migrator::down(db, None).await?;
+        Migrator::up(db, None).await?;
+        Migrator::status(db).await
+    }
+
+    /// every migration both ways, in a schema of its own on the
+    /// server of `TEST_DATABASE_URL`, on SQLite in memory without one
+    #[tokio::test]
+    async fn up_down_up() -> anyhow::Result<()> {
+        let Some(url) = &ENV_SETTINGS.test_database_url
+        else {
+            let mut options =
+                ConnectOptions::new("sqlite::memory:");
+            // every connection would open a database of its own
+            options.max_connections(1);
+            let db = Database::connect(options).await?;
+            return Ok(both_ways(&db).await?);
+        };
+        let schema = format!(
+            "migration_test_{}",
+            std::process::id()
+        );
+        let db = connect_in(url, &schema).await?;
+        let migrated = both_ways(&db).await;
+        db.execute_unprepared(&format!(
+            "DROP SCHEMA \"{}\" CASCADE",
+            schema
+        ))
+        .await?;
+        Ok(migrated?)
+    }
+
+    /// `migrate` on a `sqlite:` url
+    #[tokio::test]
+    async fn sqlite_has_no_schema() -> anyhow::Result<()> {
+        let file = std::env::temp_dir().join(format!(
+            "migration_test_{}.db",
+            std::process::id()
+        ));
+        let db = connect_in(
+            &format!(
+                "sqlite://{}?mode=rwc",
+                file.display()
+            ),
+            schema(),
+        )
+        .await?;
+        let migrated = up(&db).await;
+        db.close().await?;
+        std::fs::remove_file(&file)?;
+        Ok(migrated?)
+    }
+}
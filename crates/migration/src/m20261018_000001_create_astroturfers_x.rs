@@ -0,0 +1,95 @@
+//! baseline table, as `test_create_table` used to create it
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(
+        &self,
+        manager: &SchemaManager,
+    ) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AstroturfersX::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(
+                            AstroturfersX::UserId,
+                        )
+                        .string()
+                        .not_null()
+                        .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(AstroturfersX::Name)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(
+                            AstroturfersX::Handle,
+                        )
+                        .string()
+                        .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(
+                            AstroturfersX::ProfileUrl,
+                        )
+                        .string()
+                        .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(
+                            AstroturfersX::Avatar,
+                        )
+                        .string()
+                        .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(
+                            AstroturfersX::RegisterTime,
+                        )
+                        .string()
+                        .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(
+                            AstroturfersX::ChangedNameCount,
+                        )
+                        .integer()
+                        .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(
+        &self,
+        manager: &SchemaManager,
+    ) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(AstroturfersX::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum AstroturfersX {
+    Table,
+    UserId,
+    Name,
+    Handle,
+    ProfileUrl,
+    Avatar,
+    RegisterTime,
+    ChangedNameCount,
+}
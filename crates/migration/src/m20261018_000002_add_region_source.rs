@@ -0,0 +1,59 @@
+//! `地区：` and `来源：` of the user-meta block
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(
+        &self,
+        manager: &SchemaManager,
+    ) -> Result<(), DbErr> {
+        // one column per statement, sqlite can not add two at once
+        for column in
+            [AstroturfersX::Region, AstroturfersX::Source]
+        {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(AstroturfersX::Table)
+                        .add_column_if_not_exists(
+                            ColumnDef::new(column)
+                                .string()
+                                .not_null()
+                                .default(""),
+                        )
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(
+        &self,
+        manager: &SchemaManager,
+    ) -> Result<(), DbErr> {
+        for column in
+            [AstroturfersX::Region, AstroturfersX::Source]
+        {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(AstroturfersX::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum AstroturfersX {
+    Table,
+    Region,
+    Source,
+}
@@ -0,0 +1,181 @@
+//! `register_time` from free text to a date
+//!
+//! Values are converted with the same parser as the crawler, the rows
+//! that fail are reported and left `NULL` instead of keeping junk.
+use sea_orm_migration::prelude::*;
+use utils::tools::time::parse_date;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(
+        &self,
+        manager: &SchemaManager,
+    ) -> Result<(), DbErr> {
+        let site_tz = utils::config::env::ENV_SETTINGS
+            .x_site_tz
+            .unwrap_or(chrono_tz::UTC);
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AstroturfersX::Table)
+                    .add_column_if_not_exists(
+                        ColumnDef::new(
+                            AstroturfersX::RegisterDate,
+                        )
+                        .date()
+                        .null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        let conn = manager.get_connection();
+        let rows = conn
+            .query_all(
+                &Query::select()
+                    .column(AstroturfersX::UserId)
+                    .expr_as(
+                        Expr::col(
+                            AstroturfersX::RegisterTime,
+                        )
+                        .cast_as(Alias::new("text")),
+                        Alias::new("raw"),
+                    )
+                    .from(AstroturfersX::Table)
+                    .to_owned(),
+            )
+            .await?;
+        let mut failed = Vec::new();
+        for row in &rows {
+            let user_id: String =
+                row.try_get("", "user_id")?;
+            let raw: Option<String> =
+                row.try_get("", "raw")?;
+            let raw = raw.unwrap_or_default();
+            let Some(date) = parse_date(&raw, site_tz)
+            else {
+                log::warn!(
+                    "register_time {:?} of {} is not a date",
+                    raw,
+                    user_id
+                );
+                failed.push(user_id);
+                continue;
+            };
+            conn.execute(
+                &Query::update()
+                    .table(AstroturfersX::Table)
+                    .value(
+                        AstroturfersX::RegisterDate,
+                        date,
+                    )
+                    .and_where(
+                        Expr::col(AstroturfersX::UserId)
+                            .eq(user_id),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        }
+        if !failed.is_empty() {
+            log::warn!(
+                "{} of {} register_time values could not be parsed: {:?}",
+                failed.len(),
+                rows.len(),
+                failed
+            );
+        }
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AstroturfersX::Table)
+                    .drop_column(
+                        AstroturfersX::RegisterTime,
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AstroturfersX::Table)
+                    .rename_column(
+                        AstroturfersX::RegisterDate,
+                        AstroturfersX::RegisterTime,
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(
+        &self,
+        manager: &SchemaManager,
+    ) -> Result<(), DbErr> {
+        // sqlite can not change a column type, the text goes through
+        // a new column
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AstroturfersX::Table)
+                    .add_column(
+                        ColumnDef::new(
+                            AstroturfersX::RegisterText,
+                        )
+                        .string()
+                        .null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .get_connection()
+            .execute(
+                &Query::update()
+                    .table(AstroturfersX::Table)
+                    .value(
+                        AstroturfersX::RegisterText,
+                        Expr::col(
+                            AstroturfersX::RegisterTime,
+                        )
+                        .cast_as(Alias::new("text")),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AstroturfersX::Table)
+                    .drop_column(
+                        AstroturfersX::RegisterTime,
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AstroturfersX::Table)
+                    .rename_column(
+                        AstroturfersX::RegisterText,
+                        AstroturfersX::RegisterTime,
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum AstroturfersX {
+    Table,
+    UserId,
+    RegisterTime,
+    RegisterDate,
+    RegisterText,
+}
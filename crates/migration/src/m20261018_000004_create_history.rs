@@ -0,0 +1,158 @@
+//! identity history of astroturfers_x, seeded with the identities
+//! known today
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(
+        &self,
+        manager: &SchemaManager,
+    ) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(History::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(History::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(History::UserId)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(History::Name)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(History::Handle)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(History::Avatar)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(
+                            History::ChangedNameCount,
+                        )
+                        .integer()
+                        .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(History::FirstSeen)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(History::LastSeen)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("astroturfers_x_history_identity")
+                    .table(History::Table)
+                    .col(History::UserId)
+                    .col(History::Name)
+                    .col(History::Handle)
+                    .unique()
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        let mut seed = Query::insert();
+        seed.into_table(History::Table)
+            .columns([
+                History::UserId,
+                History::Name,
+                History::Handle,
+                History::Avatar,
+                History::ChangedNameCount,
+                History::FirstSeen,
+                History::LastSeen,
+            ])
+            .select_from(
+                Query::select()
+                    .columns([
+                        AstroturfersX::UserId,
+                        AstroturfersX::Name,
+                        AstroturfersX::Handle,
+                        AstroturfersX::Avatar,
+                        AstroturfersX::ChangedNameCount,
+                    ])
+                    .expr(Expr::current_timestamp())
+                    .expr(Expr::current_timestamp())
+                    .from(AstroturfersX::Table)
+                    // sqlite reads `ON CONFLICT` as a join
+                    // constraint without a WHERE
+                    .and_where(Expr::cust("true"))
+                    .to_owned(),
+            )
+            .map_err(|e| DbErr::Migration(e.to_string()))?
+            .on_conflict(
+                OnConflict::columns([
+                    History::UserId,
+                    History::Name,
+                    History::Handle,
+                ])
+                .do_nothing()
+                .to_owned(),
+            );
+        manager.get_connection().execute(&seed).await?;
+        Ok(())
+    }
+
+    async fn down(
+        &self,
+        manager: &SchemaManager,
+    ) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(History::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum History {
+    #[sea_orm(iden = "astroturfers_x_history")]
+    Table,
+    Id,
+    UserId,
+    Name,
+    Handle,
+    Avatar,
+    ChangedNameCount,
+    FirstSeen,
+    LastSeen,
+}
+
+#[derive(DeriveIden)]
+enum AstroturfersX {
+    Table,
+    UserId,
+    Name,
+    Handle,
+    Avatar,
+    ChangedNameCount,
+}
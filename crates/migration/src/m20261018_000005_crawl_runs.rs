@@ -0,0 +1,148 @@
+//! crawl runs and the lineage of astroturfers_x rows
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(
+        &self,
+        manager: &SchemaManager,
+    ) -> Result<(), DbErr> {
+        let counter = |column: CrawlRuns| {
+            ColumnDef::new(column)
+                .integer()
+                .not_null()
+                .default(0)
+                .to_owned()
+        };
+        manager
+            .create_table(
+                Table::create()
+                    .table(CrawlRuns::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(CrawlRuns::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(
+                            CrawlRuns::StartedAt,
+                        )
+                        .timestamp_with_time_zone()
+                        .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(
+                            CrawlRuns::FinishedAt,
+                        )
+                        .timestamp_with_time_zone()
+                        .null(),
+                    )
+                    .col(counter(CrawlRuns::PagesAttempted))
+                    .col(counter(CrawlRuns::PagesSucceeded))
+                    .col(counter(CrawlRuns::PagesFailed))
+                    .col(counter(CrawlRuns::RecordsWritten))
+                    .to_owned(),
+            )
+            .await?;
+
+        let lineage = [
+            ColumnDef::new(AstroturfersX::FirstSeenRun)
+                .big_integer()
+                .null()
+                .to_owned(),
+            ColumnDef::new(AstroturfersX::LastSeenRun)
+                .big_integer()
+                .null()
+                .to_owned(),
+            ColumnDef::new(AstroturfersX::PageUrl)
+                .string()
+                .not_null()
+                .default("")
+                .to_owned(),
+        ];
+        for mut column in lineage {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(AstroturfersX::Table)
+                        .add_column_if_not_exists(
+                            &mut column,
+                        )
+                        .to_owned(),
+                )
+                .await?;
+        }
+        manager
+            .create_index(
+                Index::create()
+                    .name("astroturfers_x_last_seen_run")
+                    .table(AstroturfersX::Table)
+                    .col(AstroturfersX::LastSeenRun)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(
+        &self,
+        manager: &SchemaManager,
+    ) -> Result<(), DbErr> {
+        // sqlite does not drop an indexed column
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("astroturfers_x_last_seen_run")
+                    .table(AstroturfersX::Table)
+                    .to_owned(),
+            )
+            .await?;
+        for column in [
+            AstroturfersX::FirstSeenRun,
+            AstroturfersX::LastSeenRun,
+            AstroturfersX::PageUrl,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(AstroturfersX::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(CrawlRuns::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum CrawlRuns {
+    Table,
+    Id,
+    StartedAt,
+    FinishedAt,
+    PagesAttempted,
+    PagesSucceeded,
+    PagesFailed,
+    RecordsWritten,
+}
+
+#[derive(DeriveIden)]
+enum AstroturfersX {
+    Table,
+    FirstSeenRun,
+    LastSeenRun,
+    PageUrl,
+}
@@ -0,0 +1,48 @@
+//! tombstone of accounts that left the list
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(
+        &self,
+        manager: &SchemaManager,
+    ) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AstroturfersX::Table)
+                    .add_column_if_not_exists(
+                        ColumnDef::new(
+                            AstroturfersX::RemovedAt,
+                        )
+                        .timestamp_with_time_zone()
+                        .null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(
+        &self,
+        manager: &SchemaManager,
+    ) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AstroturfersX::Table)
+                    .drop_column(AstroturfersX::RemovedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum AstroturfersX {
+    Table,
+    RemovedAt,
+}
@@ -0,0 +1,89 @@
+//! gzip archive of the fetched pages
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(
+        &self,
+        manager: &SchemaManager,
+    ) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(PageSnapshots::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(PageSnapshots::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(PageSnapshots::Url)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(
+                            PageSnapshots::FetchedAt,
+                        )
+                        .timestamp_with_time_zone()
+                        .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(
+                            PageSnapshots::RunId,
+                        )
+                        .big_integer()
+                        .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(
+                            PageSnapshots::HtmlGz,
+                        )
+                        .blob()
+                        .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("page_snapshots_url_fetched_at")
+                    .table(PageSnapshots::Table)
+                    .col(PageSnapshots::Url)
+                    .col(PageSnapshots::FetchedAt)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(
+        &self,
+        manager: &SchemaManager,
+    ) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(PageSnapshots::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum PageSnapshots {
+    Table,
+    Id,
+    Url,
+    FetchedAt,
+    RunId,
+    HtmlGz,
+}
@@ -0,0 +1,69 @@
+//! validators for conditional requests
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(
+        &self,
+        manager: &SchemaManager,
+    ) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(HttpCache::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(HttpCache::Url)
+                            .string()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(HttpCache::Etag)
+                            .string()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(
+                            HttpCache::LastModified,
+                        )
+                        .string()
+                        .null(),
+                    )
+                    .col(
+                        ColumnDef::new(
+                            HttpCache::UpdatedAt,
+                        )
+                        .timestamp_with_time_zone()
+                        .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(
+        &self,
+        manager: &SchemaManager,
+    ) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(HttpCache::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum HttpCache {
+    Table,
+    Url,
+    Etag,
+    LastModified,
+    UpdatedAt,
+}
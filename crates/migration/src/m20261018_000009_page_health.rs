@@ -0,0 +1,151 @@
+//! extraction health of crawl runs and their suspect pages
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(
+        &self,
+        manager: &SchemaManager,
+    ) -> Result<(), DbErr> {
+        let health = [
+            counter(CrawlRuns::CardsSeen),
+            counter(CrawlRuns::CardsRejected),
+            counter(CrawlRuns::EmptyPages),
+            ColumnDef::new(CrawlRuns::Drift)
+                .text()
+                .null()
+                .to_owned(),
+        ];
+        for column in health {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(CrawlRuns::Table)
+                        .add_column_if_not_exists(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(PageHealth::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(PageHealth::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(PageHealth::RunId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(PageHealth::Url)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(
+                            PageHealth::SnapshotId,
+                        )
+                        .big_integer()
+                        .not_null(),
+                    )
+                    .col(counter(PageHealth::Cards))
+                    .col(counter(PageHealth::Rejected))
+                    .col(
+                        ColumnDef::new(
+                            PageHealth::UnknownLabels,
+                        )
+                        .text()
+                        .not_null()
+                        .default(""),
+                    )
+                    .col(
+                        ColumnDef::new(PageHealth::Errors)
+                            .text()
+                            .not_null()
+                            .default(""),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("page_health_run_id")
+                    .table(PageHealth::Table)
+                    .col(PageHealth::RunId)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(
+        &self,
+        manager: &SchemaManager,
+    ) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(PageHealth::Table)
+                    .to_owned(),
+            )
+            .await?;
+        for column in [
+            CrawlRuns::CardsSeen,
+            CrawlRuns::CardsRejected,
+            CrawlRuns::EmptyPages,
+            CrawlRuns::Drift,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(CrawlRuns::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+fn counter(column: impl IntoIden) -> ColumnDef {
+    ColumnDef::new(column)
+        .integer()
+        .not_null()
+        .default(0)
+        .to_owned()
+}
+
+#[derive(DeriveIden)]
+enum CrawlRuns {
+    Table,
+    CardsSeen,
+    CardsRejected,
+    EmptyPages,
+    Drift,
+}
+
+#[derive(DeriveIden)]
+enum PageHealth {
+    Table,
+    Id,
+    RunId,
+    Url,
+    SnapshotId,
+    Cards,
+    Rejected,
+    UnknownLabels,
+    Errors,
+}
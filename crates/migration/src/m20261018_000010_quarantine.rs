@@ -0,0 +1,97 @@
+//! rows that failed validation, kept for review
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(
+        &self,
+        manager: &SchemaManager,
+    ) -> Result<(), DbErr> {
+        let text = |column: Quarantine| {
+            ColumnDef::new(column)
+                .string()
+                .not_null()
+                .default("")
+                .to_owned()
+        };
+        manager
+            .create_table(
+                Table::create()
+                    .table(Quarantine::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Quarantine::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Quarantine::RunId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(text(Quarantine::PageUrl))
+                    .col(text(Quarantine::UserId))
+                    .col(text(Quarantine::Name))
+                    .col(text(Quarantine::Handle))
+                    .col(text(Quarantine::ProfileUrl))
+                    .col(text(Quarantine::Avatar))
+                    .col(
+                        ColumnDef::new(Quarantine::Reasons)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(
+                            Quarantine::QuarantinedAt,
+                        )
+                        .timestamp_with_time_zone()
+                        .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("quarantine_run_id")
+                    .table(Quarantine::Table)
+                    .col(Quarantine::RunId)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(
+        &self,
+        manager: &SchemaManager,
+    ) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(Quarantine::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Quarantine {
+    Table,
+    Id,
+    RunId,
+    PageUrl,
+    UserId,
+    Name,
+    Handle,
+    ProfileUrl,
+    Avatar,
+    Reasons,
+    QuarantinedAt,
+}
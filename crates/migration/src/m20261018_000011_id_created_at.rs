@@ -0,0 +1,84 @@
+//! creation time decoded from the snowflake `user_id`
+use sea_orm_migration::prelude::*;
+use utils::tools::snowflake::parse_snowflake_time;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(
+        &self,
+        manager: &SchemaManager,
+    ) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AstroturfersX::Table)
+                    .add_column_if_not_exists(
+                        ColumnDef::new(
+                            AstroturfersX::IdCreatedAt,
+                        )
+                        .timestamp_with_time_zone()
+                        .null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        let conn = manager.get_connection();
+        let rows = conn
+            .query_all(
+                &Query::select()
+                    .column(AstroturfersX::UserId)
+                    .from(AstroturfersX::Table)
+                    .to_owned(),
+            )
+            .await?;
+        for row in &rows {
+            let user_id: String =
+                row.try_get("", "user_id")?;
+            let Some(created) =
+                parse_snowflake_time(&user_id)
+            else {
+                continue;
+            };
+            conn.execute(
+                &Query::update()
+                    .table(AstroturfersX::Table)
+                    .value(
+                        AstroturfersX::IdCreatedAt,
+                        created,
+                    )
+                    .and_where(
+                        Expr::col(AstroturfersX::UserId)
+                            .eq(user_id),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(
+        &self,
+        manager: &SchemaManager,
+    ) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AstroturfersX::Table)
+                    .drop_column(AstroturfersX::IdCreatedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum AstroturfersX {
+    Table,
+    UserId,
+    IdCreatedAt,
+}
@@ -0,0 +1,218 @@
+//! canonical handles, profile urls and avatars of the rows crawled
+//! before `normalize`
+//!
+//! History rows that become the same identity are merged into the
+//! oldest one, keeping the widest seen range.
+use chrono::{DateTime, Utc};
+use sea_orm_migration::prelude::*;
+use std::collections::BTreeMap;
+use utils::tools::x_identity as normalize;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(
+        &self,
+        manager: &SchemaManager,
+    ) -> Result<(), DbErr> {
+        let conn = manager.get_connection();
+        let rows = conn
+            .query_all(
+                &Query::select()
+                    .columns([
+                        AstroturfersX::UserId,
+                        AstroturfersX::Handle,
+                        AstroturfersX::ProfileUrl,
+                        AstroturfersX::Avatar,
+                    ])
+                    .from(AstroturfersX::Table)
+                    .to_owned(),
+            )
+            .await?;
+        for row in &rows {
+            let user_id: String =
+                row.try_get("", "user_id")?;
+            let handle: String =
+                row.try_get("", "handle")?;
+            let profile_url: String =
+                row.try_get("", "profile_url")?;
+            let avatar: String =
+                row.try_get("", "avatar")?;
+            conn.execute(
+                &Query::update()
+                    .table(AstroturfersX::Table)
+                    .values([
+                        (
+                            AstroturfersX::Handle,
+                            normalize::handle(&handle)
+                                .into(),
+                        ),
+                        (
+                            AstroturfersX::ProfileUrl,
+                            normalize::profile_url(
+                                &profile_url,
+                            )
+                            .unwrap_or(profile_url)
+                            .into(),
+                        ),
+                        (
+                            AstroturfersX::Avatar,
+                            normalize::avatar_url(&avatar)
+                                .into(),
+                        ),
+                    ])
+                    .and_where(
+                        Expr::col(AstroturfersX::UserId)
+                            .eq(user_id),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        }
+
+        merge_history(manager).await
+    }
+
+    async fn down(
+        &self,
+        _manager: &SchemaManager,
+    ) -> Result<(), DbErr> {
+        // the scraped spellings are gone, the next crawl keeps the
+        // canonical ones anyway
+        Ok(())
+    }
+}
+
+struct HistoryRow {
+    id: i64,
+    avatar: String,
+    changed_name_count: i32,
+    first_seen: DateTime<Utc>,
+    last_seen: DateTime<Utc>,
+}
+
+async fn merge_history(
+    manager: &SchemaManager<'_>,
+) -> Result<(), DbErr> {
+    let conn = manager.get_connection();
+    let rows = conn
+        .query_all(
+            &Query::select()
+                .columns([
+                    History::Id,
+                    History::UserId,
+                    History::Name,
+                    History::Handle,
+                    History::Avatar,
+                    History::ChangedNameCount,
+                    History::FirstSeen,
+                    History::LastSeen,
+                ])
+                .from(History::Table)
+                .order_by(History::Id, Order::Asc)
+                .to_owned(),
+        )
+        .await?;
+    let mut identities: BTreeMap<_, Vec<HistoryRow>> =
+        BTreeMap::new();
+    for row in &rows {
+        let user_id: String = row.try_get("", "user_id")?;
+        let name: String = row.try_get("", "name")?;
+        let handle: String = row.try_get("", "handle")?;
+        identities
+            .entry((
+                user_id,
+                name,
+                normalize::handle(&handle),
+            ))
+            .or_default()
+            .push(HistoryRow {
+                id: row.try_get("", "id")?,
+                avatar: row.try_get("", "avatar")?,
+                changed_name_count: row
+                    .try_get("", "changed_name_count")?,
+                first_seen: row
+                    .try_get("", "first_seen")?,
+                last_seen: row.try_get("", "last_seen")?,
+            });
+    }
+    for ((_, _, handle), same) in identities {
+        // rows are in id order, the first one stays
+        let keep = same[0].id;
+        let duplicates: Vec<i64> =
+            same[1..].iter().map(|row| row.id).collect();
+        if !duplicates.is_empty() {
+            conn.execute(
+                &Query::delete()
+                    .from_table(History::Table)
+                    .and_where(
+                        Expr::col(History::Id)
+                            .is_in(duplicates),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        }
+        let latest = same
+            .iter()
+            .max_by_key(|row| row.last_seen)
+            .expect("a group has a row");
+        let first_seen = same
+            .iter()
+            .map(|row| row.first_seen)
+            .min()
+            .expect("a group has a row");
+        conn.execute(
+            &Query::update()
+                .table(History::Table)
+                .values([
+                    (History::Handle, handle.into()),
+                    (
+                        History::Avatar,
+                        normalize::avatar_url(
+                            &latest.avatar,
+                        )
+                        .into(),
+                    ),
+                    (
+                        History::ChangedNameCount,
+                        latest.changed_name_count.into(),
+                    ),
+                    (History::FirstSeen, first_seen.into()),
+                    (
+                        History::LastSeen,
+                        latest.last_seen.into(),
+                    ),
+                ])
+                .and_where(Expr::col(History::Id).eq(keep))
+                .to_owned(),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+#[derive(DeriveIden)]
+enum AstroturfersX {
+    Table,
+    UserId,
+    Handle,
+    ProfileUrl,
+    Avatar,
+}
+
+#[derive(DeriveIden)]
+enum History {
+    #[sea_orm(iden = "astroturfers_x_history")]
+    Table,
+    Id,
+    UserId,
+    Name,
+    Handle,
+    Avatar,
+    ChangedNameCount,
+    FirstSeen,
+    LastSeen,
+}
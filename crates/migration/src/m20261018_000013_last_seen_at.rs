@@ -0,0 +1,72 @@
+//! when `last_seen_run` saw the account, so rows of another database
+//! compare by time, not by run id
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(
+        &self,
+        manager: &SchemaManager,
+    ) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AstroturfersX::Table)
+                    .add_column_if_not_exists(
+                        ColumnDef::new(
+                            AstroturfersX::LastSeenAt,
+                        )
+                        .timestamp_with_time_zone()
+                        .null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        // the start of the run is the closest time known
+        manager
+            .get_connection()
+            .execute(
+                &Query::update()
+                    .table(AstroturfersX::Table)
+                    .value(
+                        AstroturfersX::LastSeenAt,
+                        Expr::cust(
+                            "(SELECT started_at FROM crawl_runs \
+                             WHERE crawl_runs.id = \
+                             astroturfers_x.last_seen_run)",
+                        ),
+                    )
+                    .and_where(
+                        Expr::col(AstroturfersX::LastSeenRun)
+                            .is_not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(
+        &self,
+        manager: &SchemaManager,
+    ) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AstroturfersX::Table)
+                    .drop_column(AstroturfersX::LastSeenAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum AstroturfersX {
+    Table,
+    LastSeenRun,
+    LastSeenAt,
+}
@@ -0,0 +1,72 @@
+//! `migrate up [n]`, `migrate down [n]` and `migrate status` on the
+//! database and schema of `APP_PROFILE`, see `utils::config::profile`
+//!
+//! `down` drops data, on prod it also needs `--confirm-prod`.
+use migration::Migrator;
+use sea_orm_migration::MigratorTrait;
+use utils::config::env::ENV_SETTINGS;
+use utils::tools::log::init_logger;
+
+const USAGE: &str = "usage: migrate up [n] | down [n] [--confirm-prod] | status";
+const CONFIRM_PROD: &str = "--confirm-prod";
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    init_logger();
+    let (flags, args): (Vec<String>, Vec<String>) =
+        std::env::args()
+            .skip(1)
+            .partition(|arg| arg.starts_with("--"));
+    let confirmed = match flags.as_slice() {
+        [] => false,
+        [flag] if flag == CONFIRM_PROD => true,
+        _ => anyhow::bail!(USAGE),
+    };
+    let steps = match args.get(1) {
+        Some(n) => {
+            Some(n.parse::<u32>().map_err(|_| {
+                anyhow::anyhow!(
+                    "{} is not a step count, {}",
+                    n,
+                    USAGE
+                )
+            })?)
+        }
+        None => None,
+    };
+    let command = args.first().map(String::as_str);
+    if command == Some("down") {
+        ENV_SETTINGS
+            .guard_destructive("migrate down", confirmed)?;
+    }
+    log::info!(
+        "profile {}, schema {}",
+        ENV_SETTINGS.profile(),
+        migration::schema()
+    );
+    let db =
+        migration::connect(ENV_SETTINGS.db_url()?).await?;
+    match command {
+        Some("up") => Migrator::up(&db, steps).await?,
+        // one step unless told otherwise
+        Some("down") => {
+            Migrator::down(&db, Some(steps.unwrap_or(1)))
+                .await?
+        }
+        Some("status") => {
+            Migrator::install(&db).await?;
+            for migration in
+                Migrator::get_migration_with_status(&db)
+                    .await?
+            {
+                println!(
+                    "{:<8} {}",
+                    migration.status().to_string(),
+                    migration.name()
+                );
+            }
+        }
+        _ => anyhow::bail!(USAGE),
+    }
+    Ok(())
+}
@@ -1,2 +1,3 @@
 //! Configuration
 pub mod env;
+pub mod profile;
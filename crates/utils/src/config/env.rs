@@ -1,3 +1,5 @@
+use super::profile::Profile;
+use crate::tools::fixture::FixtureMode;
 use config::{Config, Environment};
 use serde::Deserialize;
 use std::sync::LazyLock;
@@ -5,6 +7,64 @@ use std::sync::LazyLock;
 #[derive(Debug, Deserialize)]
 pub struct EnvConfig {
     pub rust_log: Option<String>,
+    /// `dev`, `staging` or `prod`, see `profile`
+    pub app_profile: Option<Profile>,
+    /// database of the crawlers, `postgres://` or `sqlite:`
+    pub database_url: Option<String>,
+    /// Postgres url of older setups, used when `database_url` is unset
+    pub pg_db: Option<String>,
+    /// database of the staging profile, required by it
+    pub staging_database_url: Option<String>,
+    /// database of the prod profile, required by it
+    pub prod_database_url: Option<String>,
+    /// Postgres schema of every table, the profile name unless set
+    pub db_schema: Option<String>,
+    /// Postgres server for the tests, SQLite in memory unless set
+    pub test_database_url: Option<String>,
+    /// base url of the X_based_china site, point it at a mirror if needed
+    pub x_base_url: Option<String>,
+    /// upper bound of pages the X crawler will discover
+    pub x_max_page: Option<u32>,
+    /// timezone of naive timestamps on the X_based_china site
+    pub x_site_tz: Option<chrono_tz::Tz>,
+    /// extractor spec file replacing the compiled-in X_based_china one
+    pub x_extractor_spec: Option<String>,
+    /// timeout of a whole request of the shared fetcher
+    pub fetch_timeout_secs: Option<u64>,
+    pub fetch_connect_timeout_secs: Option<u64>,
+    /// retries of transient failures, after the first attempt
+    pub fetch_max_retries: Option<u32>,
+    /// `record` or `replay` the responses of the shared fetcher
+    pub fetch_fixtures: Option<FixtureMode>,
+    /// directory of the fixtures, `fixtures` unless set
+    pub fetch_fixture_dir: Option<String>,
+    /// pages without a single card a crawl run tolerates
+    pub drift_max_empty_pages: Option<u32>,
+    /// share of cards a crawl run may fail to extract
+    pub drift_max_rejected_share: Option<f64>,
+    /// distinct unknown meta labels a crawl run tolerates
+    pub drift_max_unknown_labels: Option<u32>,
+    /// rows the bulk writer buffers across pages before writing
+    pub bulk_batch_rows: Option<usize>,
+    /// smallest batch the bulk writer loads with COPY on Postgres
+    pub bulk_copy_min_rows: Option<usize>,
+    /// when a crawl run commits its rows, `streaming` by default
+    pub x_commit_mode: Option<CommitMode>,
+    /// failed pages an `atomic` run still commits with
+    pub x_atomic_max_failed_pages: Option<u32>,
+}
+
+/// when a crawl run commits its rows
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum CommitMode {
+    /// batch by batch while the run goes on
+    #[default]
+    Streaming,
+    /// all at the end of a healthy run, or nothing
+    Atomic,
 }
 
 impl EnvConfig {
@@ -0,0 +1,172 @@
+//! environment profiles, each with a database and schema of its own
+use super::env::EnvConfig;
+use serde::Deserialize;
+use std::fmt;
+
+/// where a run writes, `APP_PROFILE`
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    #[default]
+    Dev,
+    Staging,
+    Prod,
+}
+
+impl Profile {
+    /// name of the profile, also its schema unless `DB_SCHEMA` is set
+    pub fn name(self) -> &'static str {
+        match self {
+            Profile::Dev => "dev",
+            Profile::Staging => "staging",
+            Profile::Prod => "prod",
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl EnvConfig {
+    /// the profile of this run, `dev` unless set
+    pub fn profile(&self) -> Profile {
+        self.app_profile.unwrap_or_default()
+    }
+
+    /// Url of the profile database. Dev takes `DATABASE_URL`, then
+    /// `PG_DB`; staging and prod need `STAGING_DATABASE_URL` and
+    /// `PROD_DATABASE_URL`, they never write to the local database.
+    pub fn db_url(&self) -> anyhow::Result<&str> {
+        let (url, var) = match self.profile() {
+            Profile::Dev => (
+                self.database_url
+                    .as_deref()
+                    .or(self.pg_db.as_deref()),
+                "DATABASE_URL",
+            ),
+            Profile::Staging => (
+                self.staging_database_url.as_deref(),
+                "STAGING_DATABASE_URL",
+            ),
+            Profile::Prod => (
+                self.prod_database_url.as_deref(),
+                "PROD_DATABASE_URL",
+            ),
+        };
+        url.ok_or_else(|| {
+            anyhow::anyhow!(
+                "no database url for profile {}, set {}",
+                self.profile(),
+                var
+            )
+        })
+    }
+
+    /// schema of every table, `DB_SCHEMA` or the profile name
+    pub fn db_schema(&self) -> &str {
+        self.db_schema
+            .as_deref()
+            .unwrap_or(self.profile().name())
+    }
+
+    /// Refuse `operation` on the prod profile unless `confirmed`, as
+    /// with `--confirm-prod`. Every command that drops or rewrites
+    /// stored rows asks: `migrate down`, `crawl_x import` and
+    /// `crawl_x reparse`. A crawl and `migrate up` only add to prod,
+    /// the crawl tombstones nothing unless its run is complete and
+    /// healthy.
+    pub fn guard_destructive(
+        &self,
+        operation: &str,
+        confirmed: bool,
+    ) -> anyhow::Result<()> {
+        if self.profile() == Profile::Prod && !confirmed {
+            anyhow::bail!(
+                "refusing to {} on prod schema {}, confirm with --confirm-prod",
+                operation,
+                self.db_schema()
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::Config;
+
+    fn config(pairs: &[(&str, &str)]) -> EnvConfig {
+        pairs
+            .iter()
+            .fold(
+                Config::builder(),
+                |builder, (key, value)| {
+                    builder
+                        .set_override(*key, *value)
+                        .unwrap()
+                },
+            )
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap()
+    }
+
+    #[test]
+    fn profile_targets() -> anyhow::Result<()> {
+        let urls = [
+            ("database_url", "postgres://local/x"),
+            ("prod_database_url", "postgres://prod/x"),
+        ];
+        let dev = config(&urls);
+        assert_eq!(dev.profile(), Profile::Dev);
+        assert_eq!(dev.db_url()?, "postgres://local/x");
+        assert_eq!(dev.db_schema(), "dev");
+
+        let prod = config(&[
+            urls[0],
+            urls[1],
+            ("app_profile", "prod"),
+        ]);
+        assert_eq!(prod.db_url()?, "postgres://prod/x");
+        assert_eq!(prod.db_schema(), "prod");
+
+        // staging never falls back to the local database
+        let staging = config(&[
+            urls[0],
+            ("app_profile", "staging"),
+            ("db_schema", "staging_2"),
+        ]);
+        assert!(staging.db_url().is_err());
+        assert_eq!(staging.db_schema(), "staging_2");
+        assert!(config(&[]).db_url().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn prod_needs_confirmation() {
+        let dev = config(&[]);
+        assert!(
+            dev.guard_destructive("migrate down", false)
+                .is_ok()
+        );
+        let prod = config(&[("app_profile", "prod")]);
+        assert!(
+            prod.guard_destructive("migrate down", false)
+                .is_err()
+        );
+        assert!(
+            prod.guard_destructive("migrate down", true)
+                .is_ok()
+        );
+    }
+}
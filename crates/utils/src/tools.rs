@@ -1 +1,6 @@
+pub mod fetch;
+pub mod fixture;
 pub mod log;
+pub mod snowflake;
+pub mod time;
+pub mod x_identity;
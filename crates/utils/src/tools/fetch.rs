@@ -0,0 +1,580 @@
+//! shared http client of the crawlers
+//!
+//! One connection pool for the whole process, with timeouts and
+//! retries of transient failures. It can record its responses and
+//! replay them, see `fixture`.
+use super::fixture::{self, FixtureMode};
+use crate::config::env::ENV_SETTINGS;
+use rand::Rng;
+use reqwest::header::{
+    ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+    RETRY_AFTER,
+};
+use reqwest::{
+    Client, RequestBuilder, Response, ResponseBuilderExt,
+    StatusCode, Url,
+};
+use std::path::PathBuf;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct FetchConfig {
+    /// whole request, body included
+    pub timeout: Duration,
+    pub connect_timeout: Duration,
+    /// retries after the first attempt
+    pub max_retries: u32,
+    /// delay before the first retry, doubled on every retry
+    pub base_delay: Duration,
+    /// cap of a single delay, `Retry-After` included
+    pub max_delay: Duration,
+    /// record or replay the responses, neither by default
+    pub fixture_mode: Option<FixtureMode>,
+    /// where the fixtures are
+    pub fixture_dir: PathBuf,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            connect_timeout: Duration::from_secs(10),
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            fixture_mode: None,
+            fixture_dir: PathBuf::from("fixtures"),
+        }
+    }
+}
+
+impl FetchConfig {
+    /// defaults overridden by the `FETCH_*` settings
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        let settings = &ENV_SETTINGS;
+        Self {
+            timeout: settings.fetch_timeout_secs.map_or(
+                default.timeout,
+                Duration::from_secs,
+            ),
+            connect_timeout: settings
+                .fetch_connect_timeout_secs
+                .map_or(
+                    default.connect_timeout,
+                    Duration::from_secs,
+                ),
+            max_retries: settings
+                .fetch_max_retries
+                .unwrap_or(default.max_retries),
+            fixture_mode: settings.fetch_fixtures,
+            fixture_dir: settings
+                .fetch_fixture_dir
+                .as_ref()
+                .map_or(
+                    default.fixture_dir.clone(),
+                    PathBuf::from,
+                ),
+            ..default
+        }
+    }
+}
+
+/// validators of a response, for a conditional request later on
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    pub fn of(res: &Response) -> Self {
+        let header = |name| {
+            res.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(String::from)
+        };
+        Self {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+/// outcome of a conditional GET
+#[derive(Debug)]
+pub enum Conditional {
+    NotModified,
+    Modified {
+        body: String,
+        validators: Validators,
+    },
+}
+
+pub struct Fetcher {
+    client: Client,
+    config: FetchConfig,
+    /// base url of the fixture server in replay mode
+    replay: Option<Url>,
+}
+
+impl Fetcher {
+    pub fn new(
+        config: FetchConfig,
+    ) -> anyhow::Result<Self> {
+        let client = Client::builder()
+            .timeout(config.timeout)
+            .connect_timeout(config.connect_timeout)
+            .build()?;
+        let replay = match config.fixture_mode {
+            Some(FixtureMode::Replay) => Some(
+                fixture::serve(config.fixture_dir.clone())?,
+            ),
+            _ => None,
+        };
+        Ok(Self {
+            client,
+            config,
+            replay,
+        })
+    }
+
+    /// Send the request `build` makes, again after transport errors,
+    /// 408, 429 and 5xx. Any other status is returned as it is, so the
+    /// caller decides what a 404 means.
+    pub async fn send<F>(
+        &self,
+        build: F,
+    ) -> anyhow::Result<Response>
+    where
+        F: Fn(&Client) -> RequestBuilder,
+    {
+        let mut attempt = 0;
+        loop {
+            let last_try =
+                attempt >= self.config.max_retries;
+            let delay =
+                match self.send_attempt(&build).await {
+                    Ok(res)
+                        if last_try
+                            || !retryable(res.status()) =>
+                    {
+                        return Ok(res);
+                    }
+                    Ok(res) => {
+                        log::warn!(
+                            "{} answered {}, retry {}",
+                            res.url(),
+                            res.status(),
+                            attempt + 1
+                        );
+                        retry_after(&res).unwrap_or_else(
+                            || self.backoff(attempt),
+                        )
+                    }
+                    Err(e)
+                        if last_try
+                            || e.is_builder()
+                            || e.is_redirect() =>
+                    {
+                        return Err(e.into());
+                    }
+                    Err(e) => {
+                        log::warn!(
+                            "{}, retry {}",
+                            e,
+                            attempt + 1
+                        );
+                        self.backoff(attempt)
+                    }
+                };
+            tokio::time::sleep(
+                delay.min(self.config.max_delay),
+            )
+            .await;
+            attempt += 1;
+        }
+    }
+
+    /// one attempt of `send`, at the fixture server in replay mode
+    async fn send_attempt<F>(
+        &self,
+        build: &F,
+    ) -> reqwest::Result<Response>
+    where
+        F: Fn(&Client) -> RequestBuilder,
+    {
+        let mut req = build(&self.client).build()?;
+        if let Some(base) = &self.replay {
+            *req.url_mut() =
+                fixture::replay_url(base, req.url());
+        }
+        let url = req.url().clone();
+        let res = self.client.execute(req).await?;
+        match self.config.fixture_mode {
+            // a 304 has nothing to replay to a fresh crawl
+            Some(FixtureMode::Record)
+                if res.status()
+                    != StatusCode::NOT_MODIFIED =>
+            {
+                self.record(url, res).await
+            }
+            _ => Ok(res),
+        }
+    }
+
+    /// save `res` to `url` as a fixture, then hand it on
+    async fn record(
+        &self,
+        url: Url,
+        res: Response,
+    ) -> reqwest::Result<Response> {
+        let status = res.status();
+        let headers = res.headers().clone();
+        let body = res.bytes().await?;
+        if let Err(e) = fixture::save(
+            &self.config.fixture_dir,
+            &url,
+            status,
+            &headers,
+            &body,
+        ) {
+            log::warn!("no fixture of {}: {}", url, e);
+        }
+        let mut recorded = http::Response::builder()
+            .status(status)
+            .url(url);
+        if let Some(map) = recorded.headers_mut() {
+            *map = headers;
+        }
+        Ok(recorded
+            .body(body)
+            .expect("the parts of a response")
+            .into())
+    }
+
+    /// GET `url`, see [`Fetcher::send`]
+    pub async fn get(
+        &self,
+        url: &str,
+    ) -> anyhow::Result<Response> {
+        self.send(|client| client.get(url)).await
+    }
+
+    /// body of a successful GET of `url`
+    pub async fn get_text(
+        &self,
+        url: &str,
+    ) -> anyhow::Result<String> {
+        let res =
+            self.get(url).await?.error_for_status()?;
+        Ok(res.text().await?)
+    }
+
+    /// GET `url` with `If-None-Match` / `If-Modified-Since` from
+    /// `validators`, a 304 has no body to read
+    pub async fn get_conditional(
+        &self,
+        url: &str,
+        validators: &Validators,
+    ) -> anyhow::Result<Conditional> {
+        let res = self
+            .send(|client| {
+                let mut req = client.get(url);
+                if let Some(etag) = &validators.etag {
+                    req = req.header(IF_NONE_MATCH, etag);
+                }
+                if let Some(last_modified) =
+                    &validators.last_modified
+                {
+                    req = req.header(
+                        IF_MODIFIED_SINCE,
+                        last_modified,
+                    );
+                }
+                req
+            })
+            .await?;
+        if res.status() == StatusCode::NOT_MODIFIED {
+            return Ok(Conditional::NotModified);
+        }
+        let res = res.error_for_status()?;
+        let validators = Validators::of(&res);
+        Ok(Conditional::Modified {
+            body: res.text().await?,
+            validators,
+        })
+    }
+
+    /// exponential backoff with jitter, in `[delay / 2, delay]`
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .config
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.config.max_delay);
+        delay.mul_f64(rand::rng().random_range(0.5..=1.0))
+    }
+}
+
+fn retryable(status: StatusCode) -> bool {
+    status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || status.is_server_error()
+}
+
+/// `Retry-After` in seconds or as an http date
+fn retry_after(res: &Response) -> Option<Duration> {
+    let value =
+        res.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    parse_retry_after(value, chrono::Utc::now())
+}
+
+fn parse_retry_after(
+    value: &str,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at =
+        chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some((at.to_utc() - now).to_std().unwrap_or_default())
+}
+
+/// the process wide fetcher, every crawler shares its connections
+pub static FETCHER: LazyLock<Fetcher> =
+    LazyLock::new(|| {
+        Fetcher::new(FetchConfig::from_env())
+            .expect("Failed to build the http client")
+    });
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    type Requests = Arc<Mutex<Vec<String>>>;
+
+    /// answer the n-th request with `responses[n]`, the last one after
+    fn serve(
+        responses: Vec<&'static str>,
+    ) -> (String, Requests) {
+        let listener =
+            std::net::TcpListener::bind("127.0.0.1:0")
+                .unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let listener =
+            tokio::net::TcpListener::from_std(listener)
+                .unwrap();
+        let requests = Requests::default();
+        let seen = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) =
+                listener.accept().await
+            {
+                let mut buf = [0; 1024];
+                let n = stream
+                    .read(&mut buf)
+                    .await
+                    .unwrap_or(0);
+                let request =
+                    String::from_utf8_lossy(&buf[..n])
+                        .to_lowercase();
+                let n = {
+                    let mut seen = seen.lock().unwrap();
+                    seen.push(request);
+                    seen.len() - 1
+                };
+                let res =
+                    responses[n.min(responses.len() - 1)];
+                stream.write_all(res.as_bytes()).await.ok();
+            }
+        });
+        (format!("http://{}/", addr), requests)
+    }
+
+    fn quick() -> Fetcher {
+        Fetcher::new(FetchConfig {
+            max_retries: 2,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(20),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    const UNAVAILABLE: &str = "HTTP/1.1 503 Service Unavailable\r\nretry-after: 0\r\ncontent-length: 0\r\nconnection: close\r\n\r\n";
+    const NOT_FOUND: &str = "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n";
+    const OK: &str = "HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok";
+    const TAGGED: &str = "HTTP/1.1 200 OK\r\netag: \"v1\"\r\nlast-modified: Sun, 06 Nov 1994 08:49:37 GMT\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok";
+    const NOT_MODIFIED: &str = "HTTP/1.1 304 Not Modified\r\netag: \"v1\"\r\nconnection: close\r\n\r\n";
+
+    #[tokio::test]
+    async fn retries_transient_statuses()
+    -> anyhow::Result<()> {
+        let (url, requests) =
+            serve(vec![UNAVAILABLE, UNAVAILABLE, OK]);
+        assert_eq!(quick().get_text(&url).await?, "ok");
+        assert_eq!(requests.lock().unwrap().len(), 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries() {
+        let (url, requests) = serve(vec![UNAVAILABLE]);
+        let res = quick().get(&url).await.unwrap();
+        assert_eq!(
+            res.status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(requests.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_final() {
+        let (url, requests) = serve(vec![NOT_FOUND, OK]);
+        let res = quick().get(&url).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn conditional_requests() -> anyhow::Result<()> {
+        let (url, requests) =
+            serve(vec![TAGGED, NOT_MODIFIED]);
+        let fetcher = quick();
+        let first = fetcher
+            .get_conditional(&url, &Validators::default())
+            .await?;
+        let Conditional::Modified { body, validators } =
+            first
+        else {
+            panic!("first fetch must have a body");
+        };
+        assert_eq!(body, "ok");
+        assert_eq!(
+            validators.etag.as_deref(),
+            Some("\"v1\"")
+        );
+
+        let again = fetcher
+            .get_conditional(&url, &validators)
+            .await?;
+        assert!(matches!(again, Conditional::NotModified));
+        let requests = requests.lock().unwrap();
+        assert!(!requests[0].contains("if-none-match"));
+        assert!(
+            requests[1].contains("if-none-match: \"v1\"")
+        );
+        assert!(
+            requests[1]
+                .contains("if-modified-since: sun, 06 nov")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn record_and_replay() -> anyhow::Result<()> {
+        let (url, requests) = serve(vec![TAGGED]);
+        let dir = std::env::temp_dir().join(format!(
+            "fixtures_{}",
+            std::process::id()
+        ));
+        let fetcher = |mode| {
+            Fetcher::new(FetchConfig {
+                fixture_mode: Some(mode),
+                fixture_dir: dir.clone(),
+                ..Default::default()
+            })
+        };
+        let recorder = fetcher(FixtureMode::Record)?;
+        assert_eq!(recorder.get_text(&url).await?, "ok");
+
+        let replayer = fetcher(FixtureMode::Replay)?;
+        let replayed = replayer
+            .get_conditional(&url, &Validators::default())
+            .await?;
+        let tagged = Validators {
+            etag: Some("\"v1\"".to_string()),
+            last_modified: None,
+        };
+        let unchanged =
+            replayer.get_conditional(&url, &tagged).await?;
+        let missing = replayer
+            .get(&format!("{}missing", url))
+            .await?
+            .status();
+        std::fs::remove_dir_all(&dir)?;
+        let Conditional::Modified { body, validators } =
+            replayed
+        else {
+            panic!("a replayed fetch must have a body");
+        };
+        assert_eq!(body, "ok");
+        assert_eq!(
+            validators.etag.as_deref(),
+            Some("\"v1\"")
+        );
+        assert!(matches!(
+            unchanged,
+            Conditional::NotModified
+        ));
+        assert_eq!(missing, StatusCode::NOT_FOUND);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn retry_after_values() {
+        let now = chrono::DateTime::parse_from_rfc2822(
+            "Sun, 06 Nov 1994 08:49:37 GMT",
+        )
+        .unwrap()
+        .to_utc();
+        assert_eq!(
+            parse_retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after(
+                "Sun, 06 Nov 1994 08:50:07 GMT",
+                now
+            ),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_retry_after(
+                "Sun, 06 Nov 1994 08:00:00 GMT",
+                now
+            ),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn backoff_grows_and_is_capped() {
+        let fetcher = Fetcher::new(FetchConfig {
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(500),
+            ..Default::default()
+        })
+        .unwrap();
+        let first = fetcher.backoff(0);
+        assert!(first >= Duration::from_millis(50));
+        assert!(first <= Duration::from_millis(100));
+        let late = fetcher.backoff(10);
+        assert!(late >= Duration::from_millis(250));
+        assert!(late <= Duration::from_millis(500));
+    }
+}
@@ -0,0 +1,227 @@
+//! recorded http responses, so the crawlers run without the network
+//!
+//! In `record` mode the fetcher saves the final response of every
+//! request as a raw HTTP file, in `replay` mode it sends every request
+//! to a local server answering from those files. A file is named after
+//! the host, path and query of its request, a request without one gets
+//! a 404. A conditional request matching the `etag` or `last-modified`
+//! of its fixture gets a 304, like from the site.
+use reqwest::header::{
+    CONNECTION, CONTENT_LENGTH, HeaderMap,
+    TRANSFER_ENCODING,
+};
+use reqwest::{StatusCode, Url};
+use serde::Deserialize;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+
+const MISSING: &[u8] = b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n";
+const UNCHANGED: &[u8] = b"HTTP/1.1 304 Not Modified\r\ncontent-length: 0\r\nconnection: close\r\n\r\n";
+/// request headers of a conditional GET and the response headers they
+/// are checked against
+const VALIDATORS: [(&str, &str); 2] = [
+    ("if-none-match", "etag"),
+    ("if-modified-since", "last-modified"),
+];
+
+/// what the fetcher does with fixtures, `FETCH_FIXTURES`
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum FixtureMode {
+    Record,
+    Replay,
+}
+
+/// `host/path?query` of `url`, what a fixture is found by
+fn key(url: &Url) -> String {
+    let mut key = format!(
+        "{}{}",
+        url.host_str().unwrap_or_default(),
+        url.path()
+    );
+    if let Some(query) = url.query() {
+        key.push('?');
+        key.push_str(query);
+    }
+    key
+}
+
+/// File of the fixture of `key`, its `/` read `_` and every other
+/// byte but letters, digits, `.` and `-` is percent-encoded, so no two
+/// keys share a file.
+fn file_name(key: &str) -> String {
+    let mut name = String::new();
+    for byte in key.bytes() {
+        match byte {
+            b'a'..=b'z'
+            | b'A'..=b'Z'
+            | b'0'..=b'9'
+            | b'.'
+            | b'-' => name.push(byte as char),
+            b'/' => name.push('_'),
+            _ => name.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    format!("{}.http", name)
+}
+
+/// Save the response to a GET of `url` in `dir`, as the raw HTTP
+/// `serve` answers with.
+pub fn save(
+    dir: &Path,
+    url: &Url,
+    status: StatusCode,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let mut raw =
+        format!("HTTP/1.1 {}\r\n", status).into_bytes();
+    for (name, value) in headers {
+        // the body is replayed whole, on a connection of its own
+        if [CONTENT_LENGTH, TRANSFER_ENCODING, CONNECTION]
+            .contains(name)
+        {
+            continue;
+        }
+        raw.extend_from_slice(name.as_str().as_bytes());
+        raw.extend_from_slice(b": ");
+        raw.extend_from_slice(value.as_bytes());
+        raw.extend_from_slice(b"\r\n");
+    }
+    raw.extend_from_slice(
+        format!(
+            "content-length: {}\r\nconnection: close\r\n\r\n",
+            body.len()
+        )
+        .as_bytes(),
+    );
+    raw.extend_from_slice(body);
+    std::fs::write(dir.join(file_name(&key(url))), raw)
+}
+
+/// `url` on the replay server at `base`
+pub fn replay_url(base: &Url, url: &Url) -> Url {
+    let mut replay = base.clone();
+    replay.set_path(&format!(
+        "{}{}",
+        url.host_str().unwrap_or_default(),
+        url.path()
+    ));
+    replay.set_query(url.query());
+    replay
+}
+
+/// Answer from the fixtures in `dir` on a local port until the process
+/// ends, returns the base url for `replay_url`.
+pub fn serve(dir: PathBuf) -> std::io::Result<Url> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let base = Url::parse(&format!(
+        "http://{}/",
+        listener.local_addr()?
+    ))
+    .expect("a socket address makes a url");
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            if let Err(e) = answer(&dir, stream) {
+                log::warn!("fixture server: {}", e);
+            }
+        }
+    });
+    Ok(base)
+}
+
+fn answer(
+    dir: &Path,
+    mut stream: TcpStream,
+) -> std::io::Result<()> {
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // read the headers out, closing on unread data resets the client
+    let mut conditions = Vec::new();
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        if let Some(condition) =
+            name_value(&header).filter(|(name, _)| {
+                VALIDATORS.iter().any(|(c, _)| c == name)
+            })
+        {
+            conditions.push(condition);
+        }
+        header.clear();
+    }
+    // `GET /host/path?query HTTP/1.1`
+    let target = request.split(' ').nth(1).unwrap_or("/");
+    let file =
+        dir.join(file_name(target.trim_start_matches('/')));
+    let raw = std::fs::read(&file).unwrap_or_else(|_| {
+        log::warn!("no fixture {}", file.display());
+        MISSING.to_vec()
+    });
+    if unchanged(&raw, &conditions) {
+        return stream.write_all(UNCHANGED);
+    }
+    stream.write_all(&raw)
+}
+
+/// lowercase name and value of a `name: value` header line
+fn name_value(line: &str) -> Option<(String, String)> {
+    let (name, value) = line.split_once(':')?;
+    Some((
+        name.trim().to_ascii_lowercase(),
+        value.trim().to_string(),
+    ))
+}
+
+/// a condition of the request matches the validator of fixture `raw`
+fn unchanged(
+    raw: &[u8],
+    conditions: &[(String, String)],
+) -> bool {
+    let raw = String::from_utf8_lossy(raw);
+    let head =
+        raw.split("\r\n\r\n").next().unwrap_or_default();
+    let validators: Vec<(String, String)> = head
+        .lines()
+        .skip(1)
+        .filter_map(name_value)
+        .collect();
+    conditions.iter().any(|(condition, value)| {
+        VALIDATORS.iter().any(|(c, validator)| {
+            c == condition
+                && validators.iter().any(|(name, v)| {
+                    name == validator && v == value
+                })
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        let url = Url::parse(
+            "https://pluto0x0.github.io/X_based_china/page3.html?a=1",
+        )
+        .unwrap();
+        assert_eq!(
+            file_name(&key(&url)),
+            "pluto0x0.github.io_X%5Fbased%5Fchina_page3.html%3Fa%3D1.http"
+        );
+        assert_ne!(file_name("a/b"), file_name("a_b"));
+        assert_ne!(file_name("a_b"), file_name("a%5Fb"));
+        let base =
+            Url::parse("http://127.0.0.1:8080/").unwrap();
+        let replay = replay_url(&base, &url);
+        assert_eq!(
+            replay.as_str(),
+            "http://127.0.0.1:8080/pluto0x0.github.io/X_based_china/page3.html?a=1"
+        );
+    }
+}
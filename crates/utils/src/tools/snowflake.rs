@@ -0,0 +1,56 @@
+//! X (Twitter) snowflake ids
+//!
+//! A snowflake keeps the milliseconds since the X epoch in its bits
+//! above 22, the rest is worker and sequence.
+use chrono::{DateTime, Utc};
+
+/// 2010-11-04T01:42:54.657Z, the first snowflake, in ms since 1970
+const X_EPOCH_MS: i64 = 1_288_834_974_657;
+const TIMESTAMP_SHIFT: u32 = 22;
+/// sequential ids of older accounts stay far below this
+const MIN_SNOWFLAKE: u64 = 1 << 32;
+
+/// When snowflake `id` was minted, `None` for the sequential ids X
+/// handed out before snowflakes.
+pub fn snowflake_time(id: u64) -> Option<DateTime<Utc>> {
+    if id < MIN_SNOWFLAKE {
+        return None;
+    }
+    let ms = (id >> TIMESTAMP_SHIFT) as i64 + X_EPOCH_MS;
+    DateTime::from_timestamp_millis(ms)
+}
+
+/// `snowflake_time` of an id as scraped, `None` if it is not a number
+pub fn parse_snowflake_time(
+    id: &str,
+) -> Option<DateTime<Utc>> {
+    snowflake_time(id.trim().parse().ok()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode() {
+        let time =
+            snowflake_time(1830540823630675969).unwrap();
+        assert_eq!(
+            time.to_rfc3339(),
+            "2024-09-02T09:38:36.814+00:00"
+        );
+        assert_eq!(
+            parse_snowflake_time(" 1830540823630675969 "),
+            Some(time)
+        );
+    }
+
+    #[test]
+    fn not_snowflakes() {
+        // @jack, from 2006
+        assert_eq!(snowflake_time(12), None);
+        assert_eq!(snowflake_time(783214), None);
+        assert_eq!(parse_snowflake_time("ID: 1"), None);
+        assert_eq!(parse_snowflake_time(""), None);
+    }
+}
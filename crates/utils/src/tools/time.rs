@@ -0,0 +1,104 @@
+//! date and time helpers for scraped values
+use chrono::{
+    DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc,
+};
+use chrono_tz::Tz;
+
+const DATE_FORMATS: [&str; 4] =
+    ["%Y-%m-%d", "%Y/%m/%d", "%Y.%m.%d", "%Y年%m月%d日"];
+const DATETIME_FORMATS: [&str; 4] = [
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y/%m/%d %H:%M:%S",
+];
+
+/// Parse a scraped date into its UTC calendar date.
+///
+/// Plain dates are kept as they are, timestamps with an offset are
+/// converted to UTC, and naive timestamps are read as local time of
+/// `site_tz` first.
+pub fn parse_date(
+    raw: &str,
+    site_tz: Tz,
+) -> Option<NaiveDate> {
+    let raw = raw.trim();
+    if let Some(date) = DATE_FORMATS.iter().find_map(|f| {
+        NaiveDate::parse_from_str(raw, f).ok()
+    }) {
+        return Some(date);
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(raw)
+        .or_else(|_| DateTime::parse_from_rfc2822(raw))
+    {
+        return Some(time.with_timezone(&Utc).date_naive());
+    }
+    let naive = DATETIME_FORMATS.iter().find_map(|f| {
+        NaiveDateTime::parse_from_str(raw, f).ok()
+    })?;
+    let local =
+        site_tz.from_local_datetime(&naive).earliest()?;
+    Some(local.with_timezone(&Utc).date_naive())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ymd(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn plain_dates() {
+        let utc = chrono_tz::UTC;
+        assert_eq!(
+            parse_date("2024-09-02", utc),
+            Some(ymd(2024, 9, 2))
+        );
+        assert_eq!(
+            parse_date(" 2024/9/2 ", utc),
+            Some(ymd(2024, 9, 2))
+        );
+        assert_eq!(
+            parse_date("2024年09月02日", utc),
+            Some(ymd(2024, 9, 2))
+        );
+    }
+
+    #[test]
+    fn times_are_normalized_to_utc() {
+        let shanghai = chrono_tz::Asia::Shanghai;
+        assert_eq!(
+            parse_date("2024-09-02 07:30", shanghai),
+            Some(ymd(2024, 9, 1))
+        );
+        assert_eq!(
+            parse_date(
+                "2024-09-02T07:30:00+08:00",
+                chrono_tz::UTC
+            ),
+            Some(ymd(2024, 9, 1))
+        );
+        assert_eq!(
+            parse_date(
+                "2024-09-02 07:30:00",
+                chrono_tz::UTC
+            ),
+            Some(ymd(2024, 9, 2))
+        );
+    }
+
+    #[test]
+    fn junk() {
+        assert_eq!(parse_date("", chrono_tz::UTC), None);
+        assert_eq!(
+            parse_date("unknown", chrono_tz::UTC),
+            None
+        );
+        assert_eq!(
+            parse_date("2024-13-40", chrono_tz::UTC),
+            None
+        );
+    }
+}
@@ -0,0 +1,124 @@
+//! canonical forms of X (Twitter) handles, profile urls and profile
+//! images
+use reqwest::Url;
+
+/// hosts that serve X profiles
+const X_HOSTS: [&str; 5] = [
+    "x.com",
+    "www.x.com",
+    "twitter.com",
+    "www.twitter.com",
+    "mobile.twitter.com",
+];
+/// size suffixes of a profile image, without one it is the original
+const AVATAR_SIZES: [&str; 3] =
+    ["_normal", "_bigger", "_mini"];
+
+/// lowercase, without the `@`
+pub fn handle(raw: &str) -> String {
+    let raw = raw.trim();
+    raw.strip_prefix('@').unwrap_or(raw).to_lowercase()
+}
+
+/// `https://x.com/<handle>` of a profile on any X host, `None` for
+/// other urls
+pub fn profile_url(raw: &str) -> Option<String> {
+    let url = Url::parse(raw.trim()).ok()?;
+    let host = url.host_str()?.to_lowercase();
+    if !X_HOSTS.contains(&host.as_str()) {
+        return None;
+    }
+    let name = url.path_segments()?.next()?;
+    if name.is_empty() {
+        return None;
+    }
+    Some(format!("https://x.com/{}", handle(name)))
+}
+
+/// the full size image of a twimg profile image url
+pub fn avatar_url(raw: &str) -> String {
+    let raw = raw.trim();
+    let Ok(mut url) = Url::parse(raw) else {
+        return raw.to_string();
+    };
+    if url.host_str() != Some("pbs.twimg.com") {
+        return raw.to_string();
+    }
+    let path = url.path().to_string();
+    let (stem, ext) = match path.rsplit_once('.') {
+        Some((stem, ext)) if !ext.contains('/') => {
+            (stem, Some(ext))
+        }
+        _ => (path.as_str(), None),
+    };
+    let Some(stem) = AVATAR_SIZES
+        .iter()
+        .find_map(|size| stem.strip_suffix(size))
+    else {
+        return raw.to_string();
+    };
+    match ext {
+        Some(ext) => {
+            url.set_path(&format!("{}.{}", stem, ext))
+        }
+        None => url.set_path(stem),
+    }
+    url.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handles() {
+        assert_eq!(handle(" @YnHu434128 "), "ynhu434128");
+        assert_eq!(handle("ynhu434128"), "ynhu434128");
+    }
+
+    #[test]
+    fn profile_urls() {
+        for raw in [
+            "https://twitter.com/YnHu434128",
+            "http://www.twitter.com/ynhu434128?lang=zh",
+            "https://X.com/ynhu434128/#top",
+            "https://mobile.twitter.com/ynhu434128/status/1",
+        ] {
+            assert_eq!(
+                profile_url(raw).as_deref(),
+                Some("https://x.com/ynhu434128"),
+                "{}",
+                raw
+            );
+        }
+        assert_eq!(
+            profile_url("https://example.com/a"),
+            None
+        );
+        assert_eq!(profile_url("https://x.com/"), None);
+        assert_eq!(profile_url("/ynhu434128"), None);
+    }
+
+    #[test]
+    fn avatar_urls() {
+        assert_eq!(
+            avatar_url(
+                "https://pbs.twimg.com/profile_images/1963991803566186496/m8T6UVyR_normal.jpg"
+            ),
+            "https://pbs.twimg.com/profile_images/1963991803566186496/m8T6UVyR.jpg"
+        );
+        assert_eq!(
+            avatar_url(
+                "https://pbs.twimg.com/profile_images/1/a_bigger"
+            ),
+            "https://pbs.twimg.com/profile_images/1/a"
+        );
+        for kept in [
+            "https://pbs.twimg.com/profile_images/1/a.png",
+            "https://example.com/a_normal.jpg",
+            "not a url",
+        ] {
+            assert_eq!(avatar_url(kept), kept);
+        }
+    }
+}